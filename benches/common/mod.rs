@@ -0,0 +1,90 @@
+//! What the lexer and parser benches share: sources of growing size,
+//! generated by repeating a snippet or lengthening an operator chain, and
+//! a table of the time each takes.
+
+use std::time::Instant;
+
+use bull_script::lexer::Lexer;
+
+const SNIPPET: &str = r#"## Sums the numbers below `n`
+proc sum(n: int): int then
+    let total = 0 # the running total
+    for i = 0 to n then
+        if i % 2 is 0 then
+            total = total + i * 0x10 - (1_000 / 2.5e-1)
+        end
+    end
+    std.printlnval("sum of {n:>5} is {total}, ünïcödé ✓")
+    return total
+end
+"#;
+
+/// A chain of operators as long as a source of `size` bytes, which the
+/// parser nests into one expression as deep as the chain is long
+fn chain(size: usize) -> String {
+    format!("let x = 1{}\n", " + 2 * 3 - 4 / 5".repeat(size / 16))
+}
+
+/// Times `measure` on sources of growing size, first of the snippet repeated
+/// up to 8 MB and then of a single operator chain up to 1 MB, printing how
+/// long it took per byte of each along with the number of `things` it gave.
+/// Linear scaling keeps the cost per byte about the same at every size.
+pub fn scaling(things: &str, measure: impl Fn(&Lexer) -> usize + Sync) {
+    // dropping the expression of a long chain recurses as deep as it is
+    // nested, which needs more stack than the main thread has
+    std::thread::scope(|scope| {
+        std::thread::Builder::new()
+            .stack_size(1024 * 1024 * 1024)
+            .spawn_scoped(scope, || {
+                let kilobytes = |sizes: [usize; 4]| sizes.map(|size| size * 1024);
+                table(
+                    things,
+                    &measure,
+                    "snippet",
+                    kilobytes([1024, 2048, 4096, 8192]),
+                    |size| SNIPPET.repeat(size / SNIPPET.len()),
+                );
+                table(
+                    things,
+                    &measure,
+                    "chain",
+                    kilobytes([128, 256, 512, 1024]),
+                    chain,
+                );
+            })
+            .expect("the bench thread starts");
+    });
+}
+
+fn table(
+    things: &str,
+    measure: impl Fn(&Lexer) -> usize,
+    name: &str,
+    sizes: [usize; 4],
+    source: impl Fn(usize) -> String,
+) {
+    println!("{:>8} {:>10} {:>10} {:>9}", name, things, "time", "ns/byte");
+    let mut costs = vec![];
+    for size in sizes {
+        let lexer = Lexer::new("bench.bs", &source(size));
+        let start = Instant::now();
+        let count = measure(&lexer);
+        let elapsed = start.elapsed();
+
+        let cost = elapsed.as_nanos() as f64 / lexer.text.len() as f64;
+        costs.push(cost);
+        println!(
+            "{:>6}KB {:>10} {:>8.0}ms {:>9.1}",
+            size / 1024,
+            count,
+            elapsed.as_secs_f64() * 1000.0,
+            cost
+        );
+    }
+    println!(
+        "cost per byte at {}KB is {:.2}x the cost at {}KB\n",
+        sizes[3] / 1024,
+        costs[costs.len() - 1] / costs[0],
+        sizes[0] / 1024
+    );
+}
@@ -0,0 +1,10 @@
+//! Lexes generated sources of growing size to show that lexing time
+//! scales linearly with the input. Run with `cargo bench --bench lexer`.
+
+mod common;
+
+fn main() {
+    common::scaling("tokens", |lexer| {
+        lexer.lex().expect("the snippet lexes").len()
+    });
+}
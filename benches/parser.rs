@@ -0,0 +1,19 @@
+//! Parses generated scripts of growing size to show that parsing, with
+//! the lexer streaming tokens into it, scales linearly with the input.
+//! Run with `cargo bench --bench parser`.
+
+use bull_script::parser::{Expr, Parser};
+
+mod common;
+
+fn main() {
+    common::scaling("exprs", |lexer| {
+        let program = Parser::new(lexer.tokens())
+            .parse_program()
+            .expect("the snippet parses");
+        let Expr::Program(code) = program.expr else {
+            unreachable!("parse_program always returns a program")
+        };
+        code.len()
+    });
+}
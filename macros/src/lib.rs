@@ -0,0 +1,175 @@
+//! Attribute macros that declare native procs for Bull Script. They are
+//! re-exported by `bull-script` with its `macros` feature, which the code
+//! they generate relies on.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::meta::ParseNestedMeta;
+use syn::{
+    parse_macro_input, Attribute, Error, Expr, ExprLit, FnArg, ImplItem, ItemFn, ItemImpl, Lit,
+    LitStr, Meta, Signature, Type,
+};
+
+/// Reads the `name = "..."` option both attributes take
+fn name_option(meta: ParseNestedMeta, name: &mut Option<String>) -> syn::Result<()> {
+    if meta.path.is_ident("name") {
+        *name = Some(meta.value()?.parse::<LitStr>()?.value());
+        Ok(())
+    } else {
+        Err(meta.error("expected `name = \"...\"`"))
+    }
+}
+
+/// The doc comment of an item, without the space after each `///`
+fn doc(attrs: &[Attribute]) -> Option<String> {
+    let lines: Vec<String> = attrs
+        .iter()
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(meta) if meta.path.is_ident("doc") => match &meta.value {
+                Expr::Lit(ExprLit {
+                    lit: Lit::Str(line),
+                    ..
+                }) => Some(line.value()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .map(|line| line.strip_prefix(' ').unwrap_or(&line).to_string())
+        .collect();
+    (!lines.is_empty()).then(|| lines.join("\n"))
+}
+
+/// Native procs are called without an instance, so they can't take `self`
+fn check_signature(sig: &Signature) -> syn::Result<()> {
+    match sig
+        .inputs
+        .iter()
+        .find(|arg| matches!(arg, FnArg::Receiver(_)))
+    {
+        Some(receiver) => Err(Error::new_spanned(
+            receiver,
+            "a #[bull_fn] can't take `self`, as scripts call it without an instance",
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Makes a `NativeProc` for the function at `path`, called `name` in scripts
+fn native_proc(name: &str, path: TokenStream2, attrs: &[Attribute]) -> TokenStream2 {
+    let parts = name.split('.');
+    let proc = quote! {
+        ::bull_script::NativeProc::new(
+            vec![#(#parts.to_string()),*],
+            #path,
+        )
+    };
+    match doc(attrs) {
+        Some(doc) => quote!(#proc.with_doc(#doc)),
+        None => proc,
+    }
+}
+
+/// Declares the functions of an `impl` block marked with `#[bull_fn]` as
+/// native procs in a namespace, which is the type's name in lowercase unless
+/// it is given with `name = "..."`. The block gets a `bull_module()` function
+/// returning the `NativeModule` to register.
+#[proc_macro_attribute]
+pub fn bull_module(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut namespace = None;
+    let options = syn::meta::parser(|meta| name_option(meta, &mut namespace));
+    parse_macro_input!(attr with options);
+    let mut item = parse_macro_input!(item as ItemImpl);
+    match module(namespace, &mut item) {
+        Ok(module) => quote!(#item #module).into(),
+        Err(e) => {
+            let e = e.to_compile_error();
+            quote!(#item #e).into()
+        }
+    }
+}
+
+fn module(namespace: Option<String>, item: &mut ItemImpl) -> syn::Result<TokenStream2> {
+    let ty = &item.self_ty;
+    let namespace = match (namespace, ty.as_ref()) {
+        (Some(namespace), _) => namespace,
+        (None, Type::Path(path)) => match path.path.segments.last() {
+            Some(segment) => segment.ident.to_string().to_lowercase(),
+            None => return Err(Error::new_spanned(ty, "expected a type name")),
+        },
+        (None, _) => {
+            return Err(Error::new_spanned(
+                ty,
+                "this type needs a namespace, given with `name = \"...\"`",
+            ))
+        }
+    };
+
+    let mut procs = vec![];
+    for impl_item in &mut item.items {
+        let ImplItem::Fn(function) = impl_item else {
+            continue;
+        };
+        let Some(index) = function
+            .attrs
+            .iter()
+            .position(|attr| attr.path().is_ident("bull_fn"))
+        else {
+            continue;
+        };
+        // the attribute only marks the function here, so it is removed
+        // rather than expanded on its own
+        let attr = function.attrs.remove(index);
+        let mut name = None;
+        if !matches!(attr.meta, Meta::Path(_)) {
+            attr.parse_nested_meta(|meta| name_option(meta, &mut name))?;
+        }
+        check_signature(&function.sig)?;
+
+        let ident = &function.sig.ident;
+        let name = format!("{}.{}", namespace, name.unwrap_or(ident.to_string()));
+        let proc = native_proc(&name, quote!(<#ty>::#ident), &function.attrs);
+        procs.push(quote!(.with_proc(#proc)));
+    }
+
+    let (impl_generics, _, where_clause) = item.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics #ty #where_clause {
+            /// The procs marked with `#[bull_fn]`, to register with an engine
+            pub fn bull_module() -> ::bull_script::NativeModule {
+                ::bull_script::NativeModule::new(#namespace) #(#procs)*
+            }
+        }
+    })
+}
+
+/// Declares a function as a native proc, named after it unless a name like
+/// `host.add` is given with `name = "..."`. Next to the function it adds
+/// `<function>_native()`, returning the `NativeProc` to register. Inside a
+/// `#[bull_module]` it only marks which functions the module has.
+#[proc_macro_attribute]
+pub fn bull_fn(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut name = None;
+    let options = syn::meta::parser(|meta| name_option(meta, &mut name));
+    parse_macro_input!(attr with options);
+    let item = parse_macro_input!(item as ItemFn);
+    if let Err(e) = check_signature(&item.sig) {
+        let e = e.to_compile_error();
+        return quote!(#item #e).into();
+    }
+
+    let ident = &item.sig.ident;
+    let vis = &item.vis;
+    let native = format_ident!("{}_native", ident);
+    let name = name.unwrap_or(ident.to_string());
+    let proc = native_proc(&name, quote!(#ident), &item.attrs);
+    quote! {
+        #item
+
+        /// The native proc of the function this is named after
+        #vis fn #native() -> ::bull_script::NativeProc {
+            #proc
+        }
+    }
+    .into()
+}
@@ -0,0 +1,394 @@
+use std::{
+    cmp::Ordering,
+    fmt::Display,
+    ops::{Add, Mul, Neg, Sub},
+};
+
+const BASE: u64 = 1_000_000_000;
+
+/// An arbitrary-precision integer.
+///
+/// The magnitude is stored as base 10^9 limbs, least significant first,
+/// without trailing zero limbs. Zero has no limbs and is never negative.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BigInt {
+    negative: bool,
+    limbs: Vec<u32>,
+}
+
+fn trim(limbs: &mut Vec<u32>) {
+    while limbs.last() == Some(&0) {
+        limbs.pop();
+    }
+}
+
+fn cmp_abs(a: &[u32], b: &[u32]) -> Ordering {
+    if a.len() != b.len() {
+        return a.len().cmp(&b.len());
+    }
+    for (x, y) in a.iter().rev().zip(b.iter().rev()) {
+        if x != y {
+            return x.cmp(y);
+        }
+    }
+    Ordering::Equal
+}
+
+fn add_abs(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0;
+    for i in 0..a.len().max(b.len()) {
+        let sum = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        result.push((sum % BASE) as u32);
+        carry = sum / BASE;
+    }
+    if carry > 0 {
+        result.push(carry as u32);
+    }
+    result
+}
+
+/// Subtracts `b` from `a`, where `|a| >= |b|`
+fn sub_abs(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len());
+    let mut borrow = 0;
+    for (i, x) in a.iter().enumerate() {
+        let mut diff = *x as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        borrow = 0;
+        if diff < 0 {
+            diff += BASE as i64;
+            borrow = 1;
+        }
+        result.push(diff as u32);
+    }
+    trim(&mut result);
+    result
+}
+
+fn mul_abs(a: &[u32], b: &[u32]) -> Vec<u32> {
+    if a.is_empty() || b.is_empty() {
+        return vec![];
+    }
+    let mut result = vec![0u64; a.len() + b.len()];
+    for (i, x) in a.iter().enumerate() {
+        let mut carry = 0;
+        for (j, y) in b.iter().enumerate() {
+            let cur = result[i + j] + *x as u64 * *y as u64 + carry;
+            result[i + j] = cur % BASE;
+            carry = cur / BASE;
+        }
+        result[i + b.len()] += carry;
+    }
+    let mut result: Vec<u32> = result.into_iter().map(|l| l as u32).collect();
+    trim(&mut result);
+    result
+}
+
+fn mul_small(a: &[u32], n: u32) -> Vec<u32> {
+    mul_abs(a, &[n])
+}
+
+/// Long division of magnitudes, returning the quotient and remainder
+fn divmod_abs(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    let mut quotient = vec![0; a.len()];
+    let mut rem: Vec<u32> = vec![];
+    for i in (0..a.len()).rev() {
+        rem.insert(0, a[i]);
+        trim(&mut rem);
+        // find the largest digit q where b * q <= rem
+        let (mut lo, mut hi) = (0, BASE - 1);
+        while lo < hi {
+            let mid = (lo + hi).div_ceil(2);
+            if cmp_abs(&mul_small(b, mid as u32), &rem) == Ordering::Greater {
+                hi = mid - 1;
+            } else {
+                lo = mid;
+            }
+        }
+        if lo > 0 {
+            rem = sub_abs(&rem, &mul_small(b, lo as u32));
+        }
+        quotient[i] = lo as u32;
+    }
+    trim(&mut quotient);
+    (quotient, rem)
+}
+
+impl BigInt {
+    fn new(negative: bool, mut limbs: Vec<u32>) -> Self {
+        trim(&mut limbs);
+        let negative = negative && !limbs.is_empty();
+        BigInt { negative, limbs }
+    }
+
+    pub fn zero() -> Self {
+        BigInt::new(false, vec![])
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    /// How many bytes the limbs take up
+    pub fn heap_size(&self) -> usize {
+        self.limbs.len() * std::mem::size_of::<u32>()
+    }
+
+    /// Parses an optionally signed string of decimal digits
+    pub fn parse(str: &str) -> Option<Self> {
+        let (negative, digits) = match str.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, str),
+        };
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let mut limbs = vec![];
+        let mut end = digits.len();
+        while end > 0 {
+            let start = end.saturating_sub(9);
+            limbs.push(digits[start..end].parse::<u32>().ok()?);
+            end = start;
+        }
+        Some(BigInt::new(negative, limbs))
+    }
+
+    /// Parses an unsigned string of digits in the given radix
+    pub fn parse_radix(digits: &str, radix: u32) -> Option<Self> {
+        if digits.is_empty() {
+            return None;
+        }
+        let mut limbs = vec![];
+        for c in digits.chars() {
+            let digit = c.to_digit(radix)?;
+            limbs = add_abs(&mul_small(&limbs, radix), &[digit]);
+            trim(&mut limbs);
+        }
+        Some(BigInt::new(false, limbs))
+    }
+
+    pub fn to_i64(&self) -> Option<i64> {
+        let mut magnitude: i128 = 0;
+        for limb in self.limbs.iter().rev() {
+            magnitude = magnitude.checked_mul(BASE as i128)? + *limb as i128;
+            if magnitude > i64::MAX as i128 + 1 {
+                return None;
+            }
+        }
+        let value = if self.negative { -magnitude } else { magnitude };
+        i64::try_from(value).ok()
+    }
+
+    /// Converts to the nearest float, going through the decimal
+    /// representation so the result is correctly rounded
+    pub fn to_f64(&self) -> f64 {
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+
+    /// Truncating division, returning the quotient and remainder,
+    /// or `None` when dividing by zero
+    pub fn div_rem(&self, other: &BigInt) -> Option<(BigInt, BigInt)> {
+        if other.is_zero() {
+            return None;
+        }
+        let (quotient, rem) = divmod_abs(&self.limbs, &other.limbs);
+        Some((
+            BigInt::new(self.negative != other.negative, quotient),
+            BigInt::new(self.negative, rem),
+        ))
+    }
+
+    pub fn pow(&self, mut exponent: u32) -> BigInt {
+        let mut result = BigInt::from(1);
+        let mut base = self.clone();
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = &result * &base;
+            }
+            exponent >>= 1;
+            if exponent > 0 {
+                base = &base * &base;
+            }
+        }
+        result
+    }
+}
+
+impl From<i64> for BigInt {
+    fn from(value: i64) -> Self {
+        let mut magnitude = value.unsigned_abs();
+        let mut limbs = vec![];
+        while magnitude > 0 {
+            limbs.push((magnitude % BASE) as u32);
+            magnitude /= BASE;
+        }
+        BigInt::new(value < 0, limbs)
+    }
+}
+
+impl Add for &BigInt {
+    type Output = BigInt;
+
+    fn add(self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::new(self.negative, add_abs(&self.limbs, &other.limbs));
+        }
+        match cmp_abs(&self.limbs, &other.limbs) {
+            Ordering::Less => BigInt::new(other.negative, sub_abs(&other.limbs, &self.limbs)),
+            _ => BigInt::new(self.negative, sub_abs(&self.limbs, &other.limbs)),
+        }
+    }
+}
+
+impl Sub for &BigInt {
+    type Output = BigInt;
+
+    fn sub(self, other: &BigInt) -> BigInt {
+        self + &-other
+    }
+}
+
+impl Mul for &BigInt {
+    type Output = BigInt;
+
+    fn mul(self, other: &BigInt) -> BigInt {
+        BigInt::new(
+            self.negative != other.negative,
+            mul_abs(&self.limbs, &other.limbs),
+        )
+    }
+}
+
+impl Neg for &BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::new(!self.negative, self.limbs.clone())
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_abs(&self.limbs, &other.limbs),
+            (true, true) => cmp_abs(&other.limbs, &self.limbs),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Display for BigInt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Some((last, rest)) = self.limbs.split_last() else {
+            return write!(f, "0");
+        };
+        if self.negative {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", last)?;
+        for limb in rest.iter().rev() {
+            write!(f, "{:09}", limb)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(s: &str) -> BigInt {
+        BigInt::parse(s).unwrap()
+    }
+
+    #[test]
+    fn carries_across_limbs() {
+        assert_eq!(&big("999999999") + &big("1"), big("1000000000"));
+        assert_eq!(
+            &big("999999999999999999") + &big("1"),
+            big("1000000000000000000")
+        );
+        assert_eq!(
+            &big("1000000000000000000") - &big("1"),
+            big("999999999999999999")
+        );
+        assert_eq!(
+            &big("999999999") * &big("999999999"),
+            big("999999998000000001")
+        );
+        let (quotient, rem) = big("1000000000000000000").div_rem(&big("7")).unwrap();
+        assert_eq!((quotient, rem), (big("142857142857142857"), big("1")));
+    }
+
+    #[test]
+    fn negative_operands() {
+        assert_eq!(&big("-5") + &big("-7"), big("-12"));
+        assert_eq!(&big("-5") - &big("7"), big("-12"));
+        assert_eq!(&big("-5") * &big("-7"), big("35"));
+        assert_eq!(&big("-5") * &big("7"), big("-35"));
+        assert_eq!(-&big("5"), big("-5"));
+        assert!(big("-1000000000000") < big("-1"));
+        assert!(big("-1") < big("1"));
+        // truncating, so the remainder takes the sign of the dividend
+        assert_eq!(big("-7").div_rem(&big("2")), Some((big("-3"), big("-1"))));
+        assert_eq!(big("7").div_rem(&big("-2")), Some((big("-3"), big("1"))));
+        assert_eq!(big("7").div_rem(&BigInt::zero()), None);
+    }
+
+    #[test]
+    fn mixed_signs_reaching_zero() {
+        let zero = &big("1000000000000") + &big("-1000000000000");
+        assert!(zero.is_zero());
+        assert!(!zero.is_negative());
+        assert_eq!(zero, BigInt::zero());
+        assert_eq!(zero.to_string(), "0");
+        assert!(!(&big("-3") * &BigInt::zero()).is_negative());
+        assert_eq!(big("-0"), BigInt::zero());
+    }
+
+    #[test]
+    fn i64_bounds() {
+        assert_eq!(BigInt::from(i64::MAX).to_i64(), Some(i64::MAX));
+        assert_eq!(BigInt::from(i64::MIN).to_i64(), Some(i64::MIN));
+        assert_eq!(BigInt::from(i64::MIN).to_string(), "-9223372036854775808");
+        assert_eq!((&BigInt::from(i64::MAX) + &BigInt::from(1)).to_i64(), None);
+        assert_eq!((&BigInt::from(i64::MIN) - &BigInt::from(1)).to_i64(), None);
+        assert_eq!(
+            (&BigInt::from(i64::MAX) + &BigInt::from(1)).to_string(),
+            "9223372036854775808"
+        );
+        assert_eq!((-&BigInt::from(i64::MIN)).to_i64(), None);
+    }
+
+    #[test]
+    fn parse_and_display_round_trip() {
+        for s in [
+            "0",
+            "7",
+            "-7",
+            "1000000000",
+            "-1000000000",
+            "1000000001",
+            "123456789012345678901234567890",
+            "-100000000000000000000000000000000000",
+        ] {
+            assert_eq!(big(s).to_string(), s);
+        }
+        assert_eq!(big("000123").to_string(), "123");
+        assert_eq!(BigInt::parse_radix("ff", 16), Some(big("255")));
+        for s in ["", "-", "12a", "+1", "1 000"] {
+            assert_eq!(BigInt::parse(s), None, "{:?}", s);
+        }
+    }
+}
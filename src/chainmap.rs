@@ -1,10 +1,10 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, hash::Hash};
 
 pub struct ChainMap<K, V> {
     vec: Vec<HashMap<K, V>>,
 }
 
-impl<K, V> ChainMap<K, V> {
+impl<K: Eq + Hash, V> ChainMap<K, V> {
     pub fn new() -> Self {
         ChainMap { vec: vec![] }
     }
@@ -16,4 +16,32 @@ impl<K, V> ChainMap<K, V> {
     pub fn pop_hash(&mut self) {
         self.vec.pop();
     }
+
+    /// Inserts into the innermost map
+    pub fn insert(&mut self, key: K, value: V) {
+        if self.vec.is_empty() {
+            self.push_hash();
+        }
+        self.vec.last_mut().unwrap().insert(key, value);
+    }
+
+    /// Looks up a key, searching from the innermost map outwards
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.vec.iter().rev().find_map(|map| map.get(key))
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.vec.iter_mut().rev().find_map(|map| map.get_mut(key))
+    }
+
+    /// Every value in every map, including ones shadowed by inner maps
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.vec.iter().flat_map(|map| map.values())
+    }
+}
+
+impl<K: Eq + Hash, V> Default for ChainMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
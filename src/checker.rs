@@ -0,0 +1,501 @@
+use std::collections::HashMap;
+
+use crate::chainmap::ChainMap;
+use crate::error::{Error, ErrorKind};
+use crate::interpreter::LIBRARIES;
+use crate::lexer::Lexer;
+use crate::parser::{
+    BinaryOperator, Expr, ExprWL, InterpolationPart, LiteralType, Parser, Type, UnaryOperator,
+};
+
+#[derive(Debug, Clone)]
+struct Binding {
+    ty: Type,
+    /// Annotated bindings keep their type, inferred ones are widened
+    /// when assigned a value of another type
+    annotated: bool,
+}
+
+/// Infers and checks types without running the program
+pub struct Checker {
+    globals: HashMap<Vec<String>, Binding>,
+    locals: Option<ChainMap<String, Binding>>,
+    namespace: Vec<String>,
+    /// The declared return type of the proc being checked
+    ret: Option<Type>,
+    imported: Vec<String>,
+    diagnostics: Vec<Error>,
+}
+
+macro_rules! diagnostic {
+    ($self: ident, $loc: expr, $reason: expr) => {
+        $self.diagnostics.push(Error {
+            span: $loc.span.clone(),
+            reason: $reason,
+            kind: ErrorKind::Script,
+        })
+    };
+}
+
+/// Whether a value of type `actual` may be stored somewhere annotated with `expected`
+pub fn accepts(expected: &Type, actual: &Type) -> bool {
+    match (expected, actual) {
+        (Type::Any, _) | (_, Type::Any) => true,
+        (Type::Float, Type::Int | Type::BigInt) => true,
+        (Type::Int, Type::BigInt) | (Type::BigInt, Type::Int) => true,
+        (Type::List(e), Type::List(a)) | (Type::Map(e), Type::Map(a)) => match (e, a) {
+            (Some(e), Some(a)) => accepts(e, a),
+            _ => true,
+        },
+        (Type::Proc(e), Type::Proc(a)) => match (e, a) {
+            (Some((eparams, eret)), Some((aparams, aret))) => {
+                eparams.len() == aparams.len()
+                    && eparams.iter().zip(aparams).all(|(e, a)| accepts(a, e))
+                    && accepts(eret, aret)
+            }
+            _ => true,
+        },
+        (e, a) => e == a,
+    }
+}
+
+/// The type returned by conversion functions such as `int(x)`
+fn conversion_type(parts: &[String]) -> Option<Type> {
+    match parts {
+        [name] if name == "int" => Some(Type::Int),
+        [name] if name == "float" => Some(Type::Float),
+        [name] if name == "bigint" => Some(Type::BigInt),
+        [name] if name == "str" => Some(Type::Str),
+        [name] if name == "bool" => Some(Type::Bool),
+        _ => None,
+    }
+}
+
+fn is_numeric(ty: &Type) -> bool {
+    matches!(ty, Type::Int | Type::Float | Type::BigInt)
+}
+
+/// The type of `left op right`, or `None` if the operands can't be used with `op`
+fn binary_type(op: &BinaryOperator, left: &Type, right: &Type) -> Option<Type> {
+    use BinaryOperator::*;
+    match op {
+        Equal | NotEqual => return Some(Type::Bool),
+        Lesser | Greater | LesserEqual | GreaterEqual => {
+            return match (left, right) {
+                (Type::Any, _) | (_, Type::Any) => Some(Type::Bool),
+                (Type::Str, Type::Str) => Some(Type::Bool),
+                (l, r) if is_numeric(l) && is_numeric(r) => Some(Type::Bool),
+                _ => None,
+            };
+        }
+        _ => {}
+    }
+    match (op, left, right) {
+        // only strings are added to strings, though a value of type `any`
+        // may turn out to be one
+        (Add, Type::Str, Type::Str | Type::Any) | (Add, Type::Any, Type::Str) => Some(Type::Str),
+        (_, Type::Any, r) if r == &Type::Any || is_numeric(r) => Some(Type::Any),
+        (_, l, Type::Any) if is_numeric(l) => Some(Type::Any),
+        (Divide, l, r) if is_numeric(l) && is_numeric(r) => Some(Type::Float),
+        (_, Type::Int, Type::Int) => Some(Type::Int),
+        (_, Type::Int | Type::BigInt, Type::Int | Type::BigInt) => Some(Type::BigInt),
+        (_, l, r) if is_numeric(l) && is_numeric(r) => Some(Type::Float),
+        _ => None,
+    }
+}
+
+impl Checker {
+    pub fn new() -> Self {
+        Self {
+            globals: HashMap::new(),
+            locals: None,
+            namespace: vec![],
+            ret: None,
+            imported: vec![],
+            diagnostics: vec![],
+        }
+    }
+
+    /// Checks a whole program, returning every type error found
+    pub fn check_program(&mut self, prog: &ExprWL) -> Vec<Error> {
+        if let Expr::Program(code) = &prog.expr {
+            // procs and globals can be used before they are declared
+            self.declare_globals(code);
+            let found = self.diagnostics.len();
+            for ex in code {
+                self.check(ex);
+            }
+            // declaring the globals reports the same errors as checking them
+            self.diagnostics.drain(..found);
+        }
+        std::mem::take(&mut self.diagnostics)
+    }
+
+    fn declare_globals(&mut self, code: &[ExprWL]) {
+        for ex in code {
+            match &ex.expr {
+                Expr::Import(false, name) => self.import(name),
+                Expr::Namespace(name, body) => {
+                    let depth = self.namespace.len();
+                    self.namespace.extend(name.iter().cloned());
+                    self.declare_globals(body);
+                    self.namespace.truncate(depth);
+                }
+                Expr::Proc(name, params, ret, _) => {
+                    let ty = Self::proc_type(params, ret);
+                    self.declare(name, ty, true);
+                }
+                Expr::VariableDeclaration(name, ty, value)
+                | Expr::ConstantDeclaration(name, ty, value) => {
+                    let inferred = self.infer(value);
+                    self.declare(name, ty.clone().unwrap_or(inferred), ty.is_some());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn import(&mut self, name: &String) {
+        if self.imported.contains(name) {
+            return;
+        }
+        self.imported.push(name.clone());
+        if let Some((_, source)) = LIBRARIES.iter().find(|(lib, _)| lib == name) {
+            let lexer = Lexer::new(&format!("<{}>", name), source);
+            let mut parser = Parser::new(lexer.tokens());
+            if let Ok(ExprWL {
+                expr: Expr::Program(code),
+                ..
+            }) = parser.parse_program()
+            {
+                let namespace = std::mem::take(&mut self.namespace);
+                self.declare_globals(&code);
+                self.namespace = namespace;
+            }
+        }
+    }
+
+    fn proc_type(params: &[(String, Option<Type>)], ret: &Option<Type>) -> Type {
+        Type::Proc(Some((
+            params
+                .iter()
+                .map(|(_, t)| t.clone().unwrap_or(Type::Any))
+                .collect(),
+            Box::new(ret.clone().unwrap_or(Type::Any)),
+        )))
+    }
+
+    fn declare(&mut self, name: &[String], ty: Type, annotated: bool) {
+        let binding = Binding { ty, annotated };
+        match &mut self.locals {
+            Some(locals) => locals.insert(name.join("."), binding),
+            None => {
+                let mut path = self.namespace.clone();
+                path.extend(name.iter().cloned());
+                self.globals.insert(path, binding);
+            }
+        }
+    }
+
+    fn resolve_global(&self, parts: &[String]) -> Option<Vec<String>> {
+        for i in (0..=self.namespace.len()).rev() {
+            let mut path = self.namespace[..i].to_vec();
+            path.extend(parts.iter().cloned());
+            if self.globals.contains_key(&path) {
+                return Some(path);
+            }
+        }
+        None
+    }
+
+    fn lookup(&mut self, parts: &[String]) -> Option<&mut Binding> {
+        let name = parts.join(".");
+        if self.locals.as_ref().is_some_and(|l| l.get(&name).is_some()) {
+            return self.locals.as_mut().unwrap().get_mut(&name);
+        }
+        let path = self.resolve_global(parts)?;
+        self.globals.get_mut(&path)
+    }
+
+    fn check_block(&mut self, code: &[ExprWL]) {
+        if let Some(locals) = &mut self.locals {
+            locals.push_hash();
+        }
+        for ex in code {
+            self.check(ex);
+        }
+        if let Some(locals) = &mut self.locals {
+            locals.pop_hash();
+        }
+    }
+
+    fn check(&mut self, ex: &ExprWL) {
+        match &ex.expr {
+            Expr::VariableDeclaration(name, ty, value)
+            | Expr::ConstantDeclaration(name, ty, value) => {
+                let actual = self.infer(value);
+                if let Some(ty) = ty {
+                    if !accepts(ty, &actual) {
+                        diagnostic!(
+                            self,
+                            ex,
+                            format!("`{}` is declared as {}, got {}", name.join("."), ty, actual)
+                        );
+                    }
+                }
+                self.declare(name, ty.clone().unwrap_or(actual), ty.is_some());
+            }
+            Expr::Import(false, name) => self.import(name),
+            Expr::Namespace(name, body) => {
+                let depth = self.namespace.len();
+                self.namespace.extend(name.iter().cloned());
+                for ex in body {
+                    self.check(ex);
+                }
+                self.namespace.truncate(depth);
+            }
+            Expr::If(cond, body) | Expr::While(cond, body) => {
+                self.infer(cond);
+                self.check_block(body);
+            }
+            Expr::Test(_, body) => {
+                // a test has its own locals, like a proc without parameters
+                let outer_locals = self.locals.replace(ChainMap::new());
+                let outer_ret = self.ret.take();
+                self.check_block(body);
+                self.locals = outer_locals;
+                self.ret = outer_ret;
+            }
+            Expr::For(name, start, end, body) => {
+                let mut bounds = vec![];
+                for bound in [start, end] {
+                    let ty = self.infer(bound);
+                    if !accepts(&Type::Float, &ty) {
+                        diagnostic!(self, bound, format!("Expected a number, got {}", ty));
+                    }
+                    bounds.push(ty);
+                }
+                // counted as a float if either bound is one, and otherwise
+                // as the int or bigint it starts at
+                let counter = match (&bounds[0], &bounds[1]) {
+                    (Type::Float, _) | (_, Type::Float) => Type::Float,
+                    (Type::Int | Type::BigInt, Type::Int | Type::BigInt) => bounds[0].clone(),
+                    _ => Type::Any,
+                };
+                if let Some(locals) = &mut self.locals {
+                    locals.push_hash();
+                }
+                self.declare(name, counter, false);
+                self.check_block(body);
+                if let Some(locals) = &mut self.locals {
+                    locals.pop_hash();
+                }
+            }
+            Expr::Return(value) => {
+                let actual = self.infer(value);
+                if let Some(ret) = &self.ret {
+                    if !accepts(ret, &actual) {
+                        diagnostic!(
+                            self,
+                            ex,
+                            format!("Expected to return {}, got {}", ret, actual)
+                        );
+                    }
+                }
+            }
+            _ => {
+                self.infer(ex);
+            }
+        }
+    }
+
+    fn infer(&mut self, ex: &ExprWL) -> Type {
+        match &ex.expr {
+            Expr::Literal(LiteralType::String, _) => Type::Str,
+            Expr::Literal(LiteralType::Integer, _) => Type::Int,
+            Expr::Literal(LiteralType::Float, _) => Type::Float,
+            Expr::Literal(LiteralType::BigInt, _) => Type::BigInt,
+            Expr::Literal(LiteralType::Boolean, _) => Type::Bool,
+            Expr::Literal(LiteralType::Null, _) => Type::Any,
+            Expr::Group(inner) => self.infer(inner),
+            Expr::Interpolation(parts) => {
+                for part in parts {
+                    if let InterpolationPart::Value(value, _) = part {
+                        self.infer(value);
+                    }
+                }
+                Type::Str
+            }
+            Expr::Unary(UnaryOperator::LogicalNot, right) => {
+                self.infer(right);
+                Type::Bool
+            }
+            Expr::Unary(UnaryOperator::Negative, right) => {
+                let ty = self.infer(right);
+                if !accepts(&Type::Float, &ty) {
+                    diagnostic!(self, ex, format!("Cannot negate a value of type {}", ty));
+                    return Type::Any;
+                }
+                ty
+            }
+            Expr::Binary(op, left, right) => {
+                let l = self.infer(left);
+                let r = self.infer(right);
+                match binary_type(op, &l, &r) {
+                    Some(ty) => ty,
+                    None => {
+                        diagnostic!(self, ex, format!("Cannot use {:?} on {} and {}", op, l, r));
+                        Type::Any
+                    }
+                }
+            }
+            Expr::Identifier(parts) => match self.lookup(parts) {
+                Some(binding) => binding.ty.clone(),
+                None => Type::Any,
+            },
+            Expr::Call(parts, args) => self.infer_call(ex, parts, args),
+            Expr::Proc(name, params, ret, body) => {
+                let ty = Self::proc_type(params, ret);
+                self.declare(name, ty.clone(), true);
+
+                let mut locals = ChainMap::new();
+                for (param, ty) in params {
+                    locals.insert(
+                        param.clone(),
+                        Binding {
+                            ty: ty.clone().unwrap_or(Type::Any),
+                            annotated: ty.is_some(),
+                        },
+                    );
+                }
+                let outer_locals = self.locals.replace(locals);
+                let outer_ret = std::mem::replace(&mut self.ret, ret.clone());
+                self.check_block(body);
+                self.locals = outer_locals;
+                self.ret = outer_ret;
+                ty
+            }
+            Expr::VariableSet(parts, value) => {
+                let actual = self.infer(value);
+                let name = parts.join(".");
+                let mismatch = match self.lookup(parts) {
+                    Some(binding) if binding.annotated => {
+                        (!accepts(&binding.ty, &actual)).then(|| binding.ty.clone())
+                    }
+                    Some(binding) => {
+                        if !accepts(&binding.ty, &actual) {
+                            binding.ty = Type::Any;
+                        }
+                        None
+                    }
+                    None => None,
+                };
+                if let Some(ty) = mismatch {
+                    diagnostic!(
+                        self,
+                        ex,
+                        format!("`{}` is declared as {}, got {}", name, ty, actual)
+                    );
+                }
+                actual
+            }
+            _ => Type::Any,
+        }
+    }
+
+    fn infer_call(&mut self, ex: &ExprWL, parts: &[String], args: &[ExprWL]) -> Type {
+        let name = parts.join(".");
+        let callee = match conversion_type(parts) {
+            Some(ret) => Some(Type::Proc(Some((vec![Type::Any], Box::new(ret))))),
+            None => self.lookup(parts).map(|b| b.ty.clone()),
+        };
+        let actual: Vec<Type> = args.iter().map(|arg| self.infer(arg)).collect();
+        match callee {
+            Some(Type::Proc(Some((params, ret)))) => {
+                if params.len() != actual.len() {
+                    diagnostic!(
+                        self,
+                        ex,
+                        format!(
+                            "`{}` expects {} argument(s), got {}",
+                            name,
+                            params.len(),
+                            actual.len()
+                        )
+                    );
+                } else {
+                    for (i, (expected, actual)) in params.iter().zip(&actual).enumerate() {
+                        if !accepts(expected, actual) {
+                            diagnostic!(
+                                self,
+                                args[i],
+                                format!(
+                                    "Argument {} of `{}` expects {}, got {}",
+                                    i + 1,
+                                    name,
+                                    expected,
+                                    actual
+                                )
+                            );
+                        }
+                    }
+                }
+                *ret
+            }
+            Some(Type::Proc(None)) | Some(Type::Any) | None => Type::Any,
+            Some(ty) => {
+                diagnostic!(self, ex, format!("A value of type {} is not callable", ty));
+                Type::Any
+            }
+        }
+    }
+}
+
+impl Default for Checker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn check(source: &str) -> Vec<String> {
+        let lexer = Lexer::new("<test>", source);
+        let program = Parser::new(lexer.tokens()).parse_program().unwrap();
+        Checker::new()
+            .check_program(&program)
+            .into_iter()
+            .map(|e| e.reason)
+            .collect()
+    }
+
+    #[test]
+    fn adding_a_string_to_a_bool_is_an_error() {
+        assert_eq!(
+            check("let x = \"a\" + true"),
+            ["Cannot use Add on str and bool"]
+        );
+        assert_eq!(
+            check("let x = 1 + \"a\""),
+            ["Cannot use Add on int and str"]
+        );
+    }
+
+    #[test]
+    fn loop_counters_are_typed_by_their_bounds() {
+        assert!(check("for i = 0 to 3 then\n    let n: int = i\nend").is_empty());
+        assert!(check("for i = 0n to 3 then\n    let n: bigint = i\nend").is_empty());
+        assert_eq!(
+            check("for x = 0 to 1.5 then\n    let n: int = x\nend"),
+            ["`n` is declared as int, got float"]
+        );
+    }
+
+    #[test]
+    fn strings_are_added_to_strings() {
+        assert!(check("let x = \"a\" + \"b\"\nlet y: str = x + x").is_empty());
+    }
+}
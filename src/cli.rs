@@ -0,0 +1,417 @@
+//! The `bull-script` command line, which is only public so the binary can
+//! run it
+
+use crate::checker::Checker;
+use crate::cst::Cst;
+use crate::dump::{self, DumpFormat};
+use crate::error::Error;
+use crate::formatter::Formatter;
+use crate::interpreter::Interpreter;
+use crate::lexer::Lexer;
+use crate::limits::Limits;
+use crate::linter::{LintConfig, Linter};
+use crate::parser::{ExprWL, Parser};
+use crate::repl::Repl;
+use crate::testing::{find_tests, run_test, TestResult};
+use std::io::Read;
+use std::{env, fs, panic, path::Path, process, thread};
+
+const USAGE: &str = "\
+Usage: bull-script [command] [options]
+
+Commands:
+    run <file> [args...]       Run a script, passing it any arguments after its name
+    check <files...>           Type check without running
+    fmt [--check] <files...>   Format in place, or with --check list unformatted files
+    lint [--config <file>] <files...>
+                               Warn about likely mistakes, with rules from bull-lint.conf
+    test [--filter <name>] [paths...]
+                               Run the test blocks of scripts, by default those in tests/
+    repl                       Start a REPL, which is also what no arguments does
+    tokens [--format json] <file>
+                               Print the tokens of a file with their positions
+    ast [--format json] <file> Print the AST of a file as an indented tree
+
+`bull-script <file> [args...]` is short for `run`, and `-` reads a script from stdin.
+`-e <code>` runs code given on the command line instead of a file.
+
+Options:
+    -h, --help                 Print this message
+    -V, --version              Print the version
+
+Exit codes:
+    0    Success
+    1    The script failed, or the command found problems in it
+    2    The command line was invalid or a file could not be read";
+
+/// The script raised an error, or `check`, `fmt --check`, `lint` or
+/// `test` found a problem
+const EXIT_FAILURE: i32 = 1;
+/// The arguments were wrong or a file couldn't be read
+const EXIT_USAGE: i32 = 2;
+
+/// Why a command couldn't finish
+enum CliError {
+    /// An error in how the command was used, which is reported with the usage
+    Usage(String),
+    /// A script couldn't be lexed or parsed
+    Script(Error),
+}
+
+impl From<Error> for CliError {
+    fn from(error: Error) -> Self {
+        CliError::Script(error)
+    }
+}
+
+/// Reads a script, with `-` reading stdin, returning its name and text
+fn read_source(filename: &str) -> Result<(String, String), CliError> {
+    let (name, text) = if filename == "-" {
+        let mut text = String::new();
+        std::io::stdin()
+            .read_to_string(&mut text)
+            .map_err(|e| CliError::Usage(format!("Could not read stdin: {}", e)))?;
+        ("<stdin>".to_string(), text)
+    } else {
+        let text = fs::read_to_string(filename)
+            .map_err(|e| CliError::Usage(format!("Could not read {}: {}", filename, e)))?;
+        (filename.to_string(), text)
+    };
+    Ok((name, text.replace('\r', "")))
+}
+
+fn parse(name: &str, text: &str) -> Result<ExprWL, Error> {
+    let lexer = Lexer::new(name, text);
+    let mut parser = Parser::new(lexer.tokens()).with_max_stack(limits().max_stack);
+    parser.parse_program()
+}
+
+fn run(name: &str, text: &str, args: &[String]) -> i32 {
+    let mut inter = Interpreter::new()
+        .with_args(args.to_vec())
+        .with_limits(limits());
+    if let Err(e) = parse(name, text).and_then(|program| inter.run_program(program)) {
+        eprintln!("{:#}", e);
+        return EXIT_FAILURE;
+    }
+    0
+}
+
+fn no_files(command: &str) -> CliError {
+    CliError::Usage(format!("`{}` expects at least one file", command))
+}
+
+fn check(filenames: &[String]) -> Result<i32, CliError> {
+    if filenames.is_empty() {
+        return Err(no_files("check"));
+    }
+    let mut code = 0;
+    for filename in filenames {
+        let (name, text) = read_source(filename)?;
+        let program = parse(&name, &text)?;
+
+        let diagnostics = Checker::new().check_program(&program);
+        for d in &diagnostics {
+            eprintln!("{:#}", d);
+        }
+        if !diagnostics.is_empty() {
+            code = EXIT_FAILURE;
+        }
+    }
+    Ok(code)
+}
+
+/// Formats each file in place, or with `--check` only reports the files
+/// that aren't formatted. A script read from stdin is written to stdout.
+fn fmt(args: &[String]) -> Result<i32, CliError> {
+    let check = args.iter().any(|a| a == "--check");
+    let filenames: Vec<&String> = args.iter().filter(|a| *a != "--check").collect();
+    if filenames.is_empty() {
+        return Err(no_files("fmt"));
+    }
+    let mut code = 0;
+    for filename in filenames {
+        let (name, text) = read_source(filename)?;
+        let lexer = Lexer::new(&name, &text);
+        let formatted = Formatter::new(&Cst::new(&lexer)?)
+            .with_max_stack(limits().max_stack)
+            .format()?;
+        if check {
+            if formatted != text {
+                eprintln!("{} is not formatted", name);
+                code = EXIT_FAILURE;
+            }
+        } else if filename == "-" {
+            print!("{}", formatted);
+        } else if formatted != text {
+            fs::write(filename, formatted)
+                .map_err(|e| CliError::Usage(format!("Could not write {}: {}", filename, e)))?;
+        }
+    }
+    Ok(code)
+}
+
+/// The config `lint` uses when no `--config` is given, if it exists
+const LINT_CONFIG: &str = "bull-lint.conf";
+
+fn lint(args: &[String]) -> Result<i32, CliError> {
+    let mut config_file = None;
+    let mut filenames = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            match args.next() {
+                Some(file) => config_file = Some(file),
+                None => return Err(CliError::Usage("--config expects a file".to_string())),
+            }
+        } else {
+            filenames.push(arg);
+        }
+    }
+    let config = match config_file {
+        Some(file) => Some(read_source(file)?.1),
+        None => fs::read_to_string(LINT_CONFIG).ok(),
+    };
+    let config = match config.as_deref().map(LintConfig::parse) {
+        Some(Ok(config)) => config,
+        Some(Err(reason)) => {
+            return Err(CliError::Usage(format!("Invalid lint config: {}", reason)))
+        }
+        None => LintConfig::default(),
+    };
+    if filenames.is_empty() {
+        return Err(no_files("lint"));
+    }
+
+    let mut code = 0;
+    for filename in filenames {
+        let (name, text) = read_source(filename)?;
+        let program = parse(&name, &text)?;
+        let lints = Linter::new(config.clone()).lint_program(&program);
+        for l in &lints {
+            eprintln!("{:#}", l);
+        }
+        if !lints.is_empty() {
+            code = EXIT_FAILURE;
+        }
+    }
+    Ok(code)
+}
+
+/// The folder `test` looks for scripts in when it isn't given any
+const TEST_DIR: &str = "tests";
+
+/// Adds the scripts in a folder and the folders inside it, in order
+fn find_scripts(dir: &Path, scripts: &mut Vec<String>) -> Result<(), CliError> {
+    let entries = fs::read_dir(dir)
+        .map_err(|e| CliError::Usage(format!("Could not read {}: {}", dir.display(), e)))?;
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    paths.sort();
+    for path in paths {
+        if path.is_dir() {
+            find_scripts(&path, scripts)?;
+        } else if path.extension().is_some_and(|ext| ext == "bs") {
+            scripts.push(path.display().to_string());
+        }
+    }
+    Ok(())
+}
+
+/// Runs the `test` blocks of each script, each in an interpreter of its
+/// own, with `--filter` only running the tests with its text in their name
+fn test(args: &[String]) -> Result<i32, CliError> {
+    let mut filter = None;
+    let mut paths = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--filter" {
+            match args.next() {
+                Some(text) => filter = Some(text),
+                None => return Err(CliError::Usage("--filter expects a name".to_string())),
+            }
+        } else {
+            paths.push(arg.clone());
+        }
+    }
+    if paths.is_empty() {
+        paths.push(TEST_DIR.to_string());
+    }
+    let mut filenames = vec![];
+    for path in paths {
+        if Path::new(&path).is_dir() {
+            find_scripts(Path::new(&path), &mut filenames)?;
+        } else {
+            filenames.push(path);
+        }
+    }
+
+    let (mut passed, mut filtered) = (0, 0);
+    // the name and location of each failed test, with what went wrong
+    let mut failures = vec![];
+    for filename in &filenames {
+        let (name, text) = read_source(filename)?;
+        let program = match parse(&name, &text) {
+            Ok(program) => program,
+            Err(e) => {
+                println!("{} ... FAILED", name);
+                failures.push((name.clone(), e.span.clone(), format!("{:#}", e)));
+                continue;
+            }
+        };
+        for case in find_tests(&program) {
+            if filter.is_some_and(|filter| !case.name.contains(filter.as_str())) {
+                filtered += 1;
+                continue;
+            }
+            let label = format!("{}: {}", name, case.name);
+            match run_test(&program, &case, limits()) {
+                TestResult { error: None, .. } => {
+                    println!("{} ... ok", label);
+                    passed += 1;
+                }
+                TestResult {
+                    error: Some(e),
+                    output,
+                } => {
+                    println!("{} ... FAILED", label);
+                    failures.push((case.name.clone(), case.span, format!("{}{:#}", output, e)));
+                }
+            }
+        }
+    }
+
+    if !failures.is_empty() {
+        println!("\nfailures:");
+        for (label, span, report) in &failures {
+            println!("\n---- {} at {} ----\n{}", label, span, report);
+        }
+    }
+    println!(
+        "\n{} passed, {} failed, {} filtered out",
+        passed,
+        failures.len(),
+        filtered
+    );
+    Ok(if failures.is_empty() { 0 } else { EXIT_FAILURE })
+}
+
+/// The arguments of `tokens` and `ast`: an optional `--format` and a file
+fn dump_args<'a>(command: &str, args: &'a [String]) -> Result<(DumpFormat, &'a str), CliError> {
+    match args {
+        [flag, format, file] | [file, flag, format] if flag == "--format" => {
+            match DumpFormat::from_name(format) {
+                Some(format) => Ok((format, file)),
+                None => Err(CliError::Usage(format!(
+                    "Unknown format {}, expected text or json",
+                    format
+                ))),
+            }
+        }
+        [file] => Ok((DumpFormat::Text, file)),
+        _ => Err(CliError::Usage(format!(
+            "`{}` expects a single file",
+            command
+        ))),
+    }
+}
+
+fn tokens(args: &[String]) -> Result<i32, CliError> {
+    let (format, filename) = dump_args("tokens", args)?;
+    let (name, text) = read_source(filename)?;
+    print!(
+        "{}",
+        dump::tokens(Lexer::new(&name, &text).lex()?.into_iter(), format)
+    );
+    Ok(0)
+}
+
+fn ast(args: &[String]) -> Result<i32, CliError> {
+    let (format, filename) = dump_args("ast", args)?;
+    let (name, text) = read_source(filename)?;
+    print!("{}", dump::ast(&parse(&name, &text)?, format));
+    Ok(0)
+}
+
+/// `run`'s arguments: `-e <code>` or a file, then the script's arguments
+fn run_command(args: &[String]) -> Result<i32, CliError> {
+    match args {
+        [flag, code, args @ ..] if flag == "-e" => Ok(run("<inline>", code, args)),
+        [flag] if flag == "-e" => Err(CliError::Usage("-e expects some code".to_string())),
+        [file, args @ ..] => {
+            let (name, text) = read_source(file)?;
+            Ok(run(&name, &text, args))
+        }
+        [] => Err(CliError::Usage("`run` expects a file".to_string())),
+    }
+}
+
+fn cli(argv: &[String]) -> Result<i32, CliError> {
+    let Some(command) = argv.first() else {
+        Repl::new(limits()).run();
+        return Ok(0);
+    };
+    let args = &argv[1..];
+    match command.as_str() {
+        "-h" | "--help" | "help" => {
+            println!("{}", USAGE);
+            Ok(0)
+        }
+        "-V" | "--version" => {
+            println!("bull-script {}", env!("CARGO_PKG_VERSION"));
+            Ok(0)
+        }
+        "run" => run_command(args),
+        "check" => check(args),
+        "fmt" => fmt(args),
+        "lint" => lint(args),
+        "test" => test(args),
+        "repl" => {
+            Repl::new(limits()).run();
+            Ok(0)
+        }
+        "tokens" => tokens(args),
+        "ast" => ast(args),
+        "-e" | "-" => run_command(argv),
+        _ if command.starts_with('-') => {
+            Err(CliError::Usage(format!("Unknown option {}", command)))
+        }
+        // a file that is written like a command still runs
+        _ if Path::new(command).exists() || command.ends_with(".bs") => run_command(argv),
+        _ => Err(CliError::Usage(format!("Unknown command {}", command))),
+    }
+}
+
+/// The stack scripts run on, which lets them nest calls far deeper than the
+/// stack an embedded interpreter can count on by default
+const STACK_SIZE: usize = 64 * 1024 * 1024;
+
+/// Lets scripts use all of `STACK_SIZE` but what the CLI itself needs
+fn limits() -> Limits {
+    Limits::new().with_max_stack(STACK_SIZE - 4 * 1024 * 1024)
+}
+
+/// Runs the command line given to the process and exits with its code
+pub fn main() {
+    let argv: Vec<String> = env::args().skip(1).collect();
+    let cli = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || match cli(&argv) {
+            Ok(code) => code,
+            Err(CliError::Usage(reason)) => {
+                eprintln!("{}\n\nRun `bull-script --help` for usage", reason);
+                EXIT_USAGE
+            }
+            Err(CliError::Script(e)) => {
+                eprintln!("{:#}", e);
+                EXIT_FAILURE
+            }
+        })
+        .expect("the interpreter thread should start");
+    // a bug that panicked has already been reported by the panic hook
+    let code = cli
+        .join()
+        .unwrap_or_else(|payload| panic::resume_unwind(payload));
+    process::exit(code);
+}
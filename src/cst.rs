@@ -0,0 +1,149 @@
+use crate::error::Error;
+use crate::lexer::{Lexer, Token, Trivia, TriviaKind, TWL};
+use crate::parser::{ExprWL, Parser};
+use crate::source::{SourceFile, Span};
+
+/// A token along with the whitespace and comments around it. Trailing
+/// trivia runs to the end of the token's line, and everything after
+/// that leads the next token.
+#[derive(Debug, Clone)]
+pub struct CstToken {
+    pub token: TWL,
+    pub leading: Vec<Trivia>,
+    pub trailing: Vec<Trivia>,
+}
+
+/// A lossless view of a file: every token with the trivia around it, so
+/// the exact source can be rebuilt and the AST derived from it
+#[derive(Debug, Clone)]
+pub struct Cst {
+    pub file: SourceFile,
+    /// The tokens in source order, ending with EOF, which leads with
+    /// any trivia at the end of the file
+    pub tokens: Vec<CstToken>,
+}
+
+impl Cst {
+    pub fn new(lexer: &Lexer) -> Result<Self, Error> {
+        let mut scanner = lexer.tokens().with_trivia();
+        let mut tokens: Vec<CstToken> = vec![];
+        while let Some(token) = scanner.next() {
+            let token = token?;
+            let mut leading = scanner.take_trivia();
+            if let Some(previous) = tokens.last_mut() {
+                let line_end = leading
+                    .iter()
+                    .position(|t| t.kind == TriviaKind::Newline)
+                    .unwrap_or(leading.len());
+                previous.trailing = leading.drain(..line_end).collect();
+            }
+            tokens.push(CstToken {
+                token,
+                leading,
+                trailing: vec![],
+            });
+        }
+        Ok(Cst {
+            file: lexer.file.clone(),
+            tokens,
+        })
+    }
+
+    /// Rebuilds the source text exactly as it was lexed
+    pub fn text(&self) -> String {
+        let source = self.file.text();
+        let mut text = String::with_capacity(source.len());
+        for token in &self.tokens {
+            for trivia in &token.leading {
+                text.push_str(&source[trivia.span.start..trivia.span.end]);
+            }
+            text.push_str(&source[token.token.span.start..token.token.span.end]);
+            for trivia in &token.trailing {
+                text.push_str(&source[trivia.span.start..trivia.span.end]);
+            }
+        }
+        text
+    }
+
+    /// Derives the AST by parsing the tokens without their trivia
+    pub fn to_ast(&self) -> Result<ExprWL, Error> {
+        self.parser().parse_program()
+    }
+
+    /// A parser of the tokens without their trivia, for parsing them with
+    /// other settings than `to_ast` does
+    pub fn parser(&self) -> Parser<'_> {
+        Parser::new(self.tokens.iter().map(|t| Ok(t.token.clone())))
+    }
+
+    /// The tokens that make up a span, such as the span of an expression
+    pub fn tokens_in(&self, span: Span) -> &[CstToken] {
+        let start = self
+            .tokens
+            .partition_point(|t| t.token.span.start < span.start);
+        let end = self.tokens.partition_point(|t| {
+            t.token.span.end <= span.end && !matches!(t.token.token, Token::EOF())
+        });
+        &self.tokens[start..end.max(start)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(source: &str) {
+        let cst = Cst::new(&Lexer::new("<test>", source)).unwrap();
+        assert_eq!(cst.text(), source);
+    }
+
+    #[test]
+    fn scripts_in_the_repo() {
+        let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+        for dir in ["tests", "examples", "src/lib"] {
+            for entry in std::fs::read_dir(root.join(dir)).unwrap() {
+                let path = entry.unwrap().path();
+                if path.extension().is_some_and(|ext| ext == "bs") {
+                    round_trip(&std::fs::read_to_string(&path).unwrap());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn trivia_heavy_sources() {
+        for source in [
+            "",
+            "\n\n",
+            "# only a comment",
+            "#[ only a block ]#",
+            "let x = 1",
+            "  \t let x = 1   \n\n\n",
+            "let x = 1\r\nlet y = 2\r\n",
+            "## doc\n##\n## more\nproc f() then # why\n    #[ a #[ nested ]# block\n ]# return 1 #[ tail ]#\nend   \t",
+            "let s = \"{x:>5} ünïcödé ✓\" # ✓\n\t\tlet t = r\"\\n\" + \"\"\"\n    text\n    \"\"\"",
+            "let c = 'é'\n\n\n# trailing comment at the end",
+        ] {
+            round_trip(source);
+        }
+    }
+
+    #[test]
+    fn comments_trail_their_line_and_lead_the_next() {
+        let cst = Cst::new(&Lexer::new("<test>", "a # one\n# two\nb")).unwrap();
+        let kinds = |trivia: &[Trivia]| trivia.iter().map(|t| t.kind).collect::<Vec<_>>();
+        assert_eq!(
+            kinds(&cst.tokens[0].trailing),
+            [TriviaKind::Whitespace, TriviaKind::LineComment]
+        );
+        assert_eq!(
+            kinds(&cst.tokens[1].leading),
+            [
+                TriviaKind::Newline,
+                TriviaKind::LineComment,
+                TriviaKind::Newline
+            ]
+        );
+        assert!(cst.tokens[1].trailing.is_empty());
+    }
+}
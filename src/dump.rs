@@ -0,0 +1,570 @@
+use std::fmt::{Display, Write};
+
+use crate::lexer::{StringPart, Token, TWL};
+use crate::parser::{Alignment, Expr, ExprWL, FormatSpec, InterpolationPart, Type};
+use crate::source::Span;
+
+/// How the `tokens` and `ast` commands print what they find
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DumpFormat {
+    /// Readable text, one token per line or an indented tree
+    Text,
+    /// JSON for tools to read
+    Json,
+}
+
+impl DumpFormat {
+    pub fn from_name(name: &str) -> Option<DumpFormat> {
+        match name {
+            "text" => Some(DumpFormat::Text),
+            "json" => Some(DumpFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Just enough JSON to describe tokens and expressions
+enum Json {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(&'static str, Json)>),
+}
+
+impl Json {
+    fn string(s: impl Into<String>) -> Json {
+        Json::String(s.into())
+    }
+
+    fn number(n: impl Display) -> Json {
+        let n = n.to_string();
+        // JSON has no infinity or NaN
+        if n.parse::<f64>().is_ok_and(|n| n.is_finite()) {
+            Json::Number(n)
+        } else {
+            Json::String(n)
+        }
+    }
+
+    fn write(&self, out: &mut String, indent: usize) {
+        let pad = |out: &mut String, indent: usize| out.push_str(&"  ".repeat(indent));
+        match self {
+            Json::Null => out.push_str("null"),
+            Json::Bool(b) => _ = write!(out, "{}", b),
+            Json::Number(n) => out.push_str(n),
+            Json::String(s) => {
+                out.push('"');
+                for c in s.chars() {
+                    match c {
+                        '"' => out.push_str("\\\""),
+                        '\\' => out.push_str("\\\\"),
+                        '\n' => out.push_str("\\n"),
+                        '\r' => out.push_str("\\r"),
+                        '\t' => out.push_str("\\t"),
+                        c if (c as u32) < 0x20 => _ = write!(out, "\\u{:04x}", c as u32),
+                        c => out.push(c),
+                    }
+                }
+                out.push('"');
+            }
+            Json::Array(items) if items.is_empty() => out.push_str("[]"),
+            Json::Array(items) => {
+                out.push_str("[\n");
+                for (i, item) in items.iter().enumerate() {
+                    pad(out, indent + 1);
+                    item.write(out, indent + 1);
+                    out.push_str(if i + 1 < items.len() { ",\n" } else { "\n" });
+                }
+                pad(out, indent);
+                out.push(']');
+            }
+            Json::Object(fields) => {
+                out.push_str("{\n");
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    pad(out, indent + 1);
+                    Json::string(*key).write(out, indent + 1);
+                    out.push_str(": ");
+                    value.write(out, indent + 1);
+                    out.push_str(if i + 1 < fields.len() { ",\n" } else { "\n" });
+                }
+                pad(out, indent);
+                out.push('}');
+            }
+        }
+    }
+}
+
+impl Display for Json {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut out = String::new();
+        self.write(&mut out, 0);
+        f.write_str(&out)
+    }
+}
+
+fn span_json(span: &Span) -> Json {
+    let position = span.line_col();
+    Json::Object(vec![
+        ("file", Json::string(span.file.name())),
+        ("line", optional(&position, |(line, _)| Json::number(*line))),
+        (
+            "column",
+            optional(&position, |(_, column)| Json::number(*column)),
+        ),
+        ("start", Json::number(span.start)),
+        ("end", Json::number(span.end)),
+    ])
+}
+
+fn optional<T>(value: &Option<T>, f: impl FnOnce(&T) -> Json) -> Json {
+    value.as_ref().map_or(Json::Null, f)
+}
+
+/// The name of a token without its value, such as `Identifier`
+fn token_kind(token: &Token) -> String {
+    let debug = format!("{:?}", token);
+    debug.split('(').next().unwrap_or(&debug).to_string()
+}
+
+fn token_json(token: &TWL) -> Json {
+    let value = match &token.token {
+        Token::Identifier(parts) => Json::string(parts.join(".")),
+        Token::Unknown(s)
+        | Token::LibraryPath(s)
+        | Token::RelativePath(s)
+        | Token::StringLiteral(s)
+        | Token::BigIntLiteral(s) => Json::string(s.clone()),
+        Token::CharLiteral(c) => Json::string(c.to_string()),
+        Token::IntegerLiteral(i) => Json::number(i),
+        Token::FloatLiteral(f) => Json::number(f),
+        Token::BooleanLiteral(b) => Json::Bool(*b),
+        Token::BuiltinType(name) => Json::string(name),
+        Token::InterpolatedString(parts) => Json::Array(
+            parts
+                .iter()
+                .map(|part| match part {
+                    StringPart::Text(text) => {
+                        Json::Object(vec![("text", Json::string(text.clone()))])
+                    }
+                    StringPart::Value(tokens, spec) => Json::Object(vec![
+                        (
+                            "tokens",
+                            Json::Array(tokens.iter().map(token_json).collect()),
+                        ),
+                        ("spec", optional(spec, |spec| Json::string(spec.clone()))),
+                    ]),
+                })
+                .collect(),
+        ),
+        _ => Json::Null,
+    };
+    Json::Object(vec![
+        ("kind", Json::string(token_kind(&token.token))),
+        ("value", value),
+        ("span", span_json(&token.span)),
+    ])
+}
+
+/// Prints each token with the line and column it starts at
+pub fn tokens(tokens: impl Iterator<Item = TWL>, format: DumpFormat) -> String {
+    match format {
+        DumpFormat::Text => {
+            let mut out = String::new();
+            for token in tokens {
+                let (line, column) = token.span.line_col().unwrap_or_default();
+                let position = format!("{}:{}", line, column);
+                _ = writeln!(out, "{:<8} {:?}", position, token.token);
+            }
+            out
+        }
+        DumpFormat::Json => format!(
+            "{}\n",
+            Json::Array(tokens.map(|t| token_json(&t)).collect())
+        ),
+    }
+}
+
+fn type_json(ty: &Option<Type>) -> Json {
+    optional(ty, |ty| Json::string(ty.to_string()))
+}
+
+fn spec_json(spec: &FormatSpec) -> Json {
+    let align = spec.align.as_ref().map(|align| match align {
+        Alignment::Left => "left",
+        Alignment::Right => "right",
+        Alignment::Center => "center",
+    });
+    Json::Object(vec![
+        ("fill", Json::string(spec.fill.to_string())),
+        ("align", optional(&align, |align| Json::string(*align))),
+        ("zero", Json::Bool(spec.zero)),
+        ("width", Json::number(spec.width)),
+        ("precision", optional(&spec.precision, |p| Json::number(p))),
+    ])
+}
+
+fn block_json(code: &[ExprWL]) -> Json {
+    Json::Array(code.iter().map(expr_json).collect())
+}
+
+fn expr_json(ex: &ExprWL) -> Json {
+    let name = |name: &[String]| Json::string(name.join("."));
+    let (kind, mut fields) = match &ex.expr {
+        Expr::Literal(ty, value) => (
+            "Literal",
+            vec![
+                ("type", Json::string(format!("{:?}", ty))),
+                ("value", Json::string(value.clone())),
+            ],
+        ),
+        Expr::Program(code) => ("Program", vec![("body", block_json(code))]),
+        Expr::Group(inner) => ("Group", vec![("expr", expr_json(inner))]),
+        Expr::Unary(op, right) => (
+            "Unary",
+            vec![
+                ("op", Json::string(format!("{:?}", op))),
+                ("expr", expr_json(right)),
+            ],
+        ),
+        Expr::Binary(op, left, right) => (
+            "Binary",
+            vec![
+                ("op", Json::string(format!("{:?}", op))),
+                ("left", expr_json(left)),
+                ("right", expr_json(right)),
+            ],
+        ),
+        Expr::Identifier(parts) => ("Identifier", vec![("name", name(parts))]),
+        Expr::Call(parts, args) => (
+            "Call",
+            vec![("name", name(parts)), ("args", block_json(args))],
+        ),
+        Expr::Import(relative, path) => (
+            "Import",
+            vec![
+                ("relative", Json::Bool(*relative)),
+                ("path", Json::string(path.clone())),
+            ],
+        ),
+        Expr::Proc(parts, params, ret, body) => (
+            "Proc",
+            vec![
+                ("name", name(parts)),
+                (
+                    "params",
+                    Json::Array(
+                        params
+                            .iter()
+                            .map(|(param, ty)| {
+                                Json::Object(vec![
+                                    ("name", Json::string(param.clone())),
+                                    ("type", type_json(ty)),
+                                ])
+                            })
+                            .collect(),
+                    ),
+                ),
+                ("returns", type_json(ret)),
+                ("body", block_json(body)),
+            ],
+        ),
+        Expr::If(cond, body) => (
+            "If",
+            vec![("condition", expr_json(cond)), ("body", block_json(body))],
+        ),
+        Expr::For(var, start, end, body) => (
+            "For",
+            vec![
+                ("variable", name(var)),
+                ("start", expr_json(start)),
+                ("end", expr_json(end)),
+                ("body", block_json(body)),
+            ],
+        ),
+        Expr::While(cond, body) => (
+            "While",
+            vec![("condition", expr_json(cond)), ("body", block_json(body))],
+        ),
+        Expr::Test(test, body) => (
+            "Test",
+            vec![
+                ("name", Json::string(test.clone())),
+                ("body", block_json(body)),
+            ],
+        ),
+        Expr::Return(value) => ("Return", vec![("value", expr_json(value))]),
+        Expr::VariableDeclaration(var, ty, value) => (
+            "VariableDeclaration",
+            vec![
+                ("name", name(var)),
+                ("type", type_json(ty)),
+                ("value", expr_json(value)),
+            ],
+        ),
+        Expr::ConstantDeclaration(var, ty, value) => (
+            "ConstantDeclaration",
+            vec![
+                ("name", name(var)),
+                ("type", type_json(ty)),
+                ("value", expr_json(value)),
+            ],
+        ),
+        Expr::VariableSet(var, value) => (
+            "VariableSet",
+            vec![("name", name(var)), ("value", expr_json(value))],
+        ),
+        Expr::Namespace(parts, body) => (
+            "Namespace",
+            vec![("name", name(parts)), ("body", block_json(body))],
+        ),
+        Expr::Interpolation(parts) => (
+            "Interpolation",
+            vec![(
+                "parts",
+                Json::Array(
+                    parts
+                        .iter()
+                        .map(|part| match part {
+                            InterpolationPart::Text(text) => {
+                                Json::Object(vec![("text", Json::string(text.clone()))])
+                            }
+                            InterpolationPart::Value(value, spec) => Json::Object(vec![
+                                ("value", expr_json(value)),
+                                ("spec", optional(spec, spec_json)),
+                            ]),
+                        })
+                        .collect(),
+                ),
+            )],
+        ),
+    };
+    let mut object = vec![("kind", Json::string(kind))];
+    object.append(&mut fields);
+    if let Some(doc) = &ex.doc {
+        object.push(("doc", Json::string(doc.clone())));
+    }
+    object.push(("span", span_json(&ex.span)));
+    Json::Object(object)
+}
+
+/// A one line description of an expression, without its children
+fn expr_label(ex: &ExprWL) -> String {
+    match &ex.expr {
+        Expr::Literal(ty, value) => format!("Literal {:?} {:?}", ty, value),
+        Expr::Program(_) => "Program".to_string(),
+        Expr::Group(_) => "Group".to_string(),
+        Expr::Unary(op, _) => format!("Unary {:?}", op),
+        Expr::Binary(op, _, _) => format!("Binary {:?}", op),
+        Expr::Identifier(name) => format!("Identifier {}", name.join(".")),
+        Expr::Call(name, _) => format!("Call {}", name.join(".")),
+        Expr::Import(true, path) => format!("Import {:?}", path),
+        Expr::Import(false, path) => format!("Import {}", path),
+        Expr::Proc(name, params, ret, _) => {
+            let params: Vec<String> = params
+                .iter()
+                .map(|(param, ty)| match ty {
+                    Some(ty) => format!("{}: {}", param, ty),
+                    None => param.clone(),
+                })
+                .collect();
+            let ret = ret.as_ref().map_or(String::new(), |ty| format!(": {}", ty));
+            format!("Proc {}({}){}", name.join("."), params.join(", "), ret)
+        }
+        Expr::If(..) => "If".to_string(),
+        Expr::For(name, ..) => format!("For {}", name.join(".")),
+        Expr::While(..) => "While".to_string(),
+        Expr::Return(_) => "Return".to_string(),
+        Expr::VariableDeclaration(name, ty, _) | Expr::ConstantDeclaration(name, ty, _) => {
+            let keyword = match ex.expr {
+                Expr::VariableDeclaration(..) => "Let",
+                _ => "Const",
+            };
+            let ty = ty.as_ref().map_or(String::new(), |ty| format!(": {}", ty));
+            format!("{} {}{}", keyword, name.join("."), ty)
+        }
+        Expr::VariableSet(name, _) => format!("Set {}", name.join(".")),
+        Expr::Namespace(name, _) => format!("Namespace {}", name.join(".")),
+        Expr::Interpolation(_) => "Interpolation".to_string(),
+        Expr::Test(name, _) => format!("Test {:?}", name),
+    }
+}
+
+fn write_tree(out: &mut String, ex: &ExprWL, depth: usize) {
+    let (line, column) = ex.span.line_col().unwrap_or_default();
+    _ = writeln!(
+        out,
+        "{}{} @ {}:{}",
+        "  ".repeat(depth),
+        expr_label(ex),
+        line,
+        column
+    );
+    let mut child = |ex: &ExprWL| write_tree(out, ex, depth + 1);
+    match &ex.expr {
+        Expr::Program(code)
+        | Expr::Namespace(_, code)
+        | Expr::Proc(_, _, _, code)
+        | Expr::Test(_, code) => code.iter().for_each(child),
+        Expr::Call(_, args) => args.iter().for_each(child),
+        Expr::Group(inner)
+        | Expr::Unary(_, inner)
+        | Expr::Return(inner)
+        | Expr::VariableDeclaration(_, _, inner)
+        | Expr::ConstantDeclaration(_, _, inner)
+        | Expr::VariableSet(_, inner) => child(inner),
+        Expr::Binary(_, left, right) => {
+            child(left);
+            child(right);
+        }
+        Expr::If(cond, body) | Expr::While(cond, body) => {
+            child(cond);
+            body.iter().for_each(child);
+        }
+        Expr::For(_, start, end, body) => {
+            child(start);
+            child(end);
+            body.iter().for_each(child);
+        }
+        Expr::Interpolation(parts) => {
+            for part in parts {
+                match part {
+                    InterpolationPart::Text(text) => {
+                        _ = writeln!(out, "{}Text {:?}", "  ".repeat(depth + 1), text);
+                    }
+                    InterpolationPart::Value(value, _) => write_tree(out, value, depth + 1),
+                }
+            }
+        }
+        Expr::Literal(..) | Expr::Identifier(_) | Expr::Import(..) => {}
+    }
+}
+
+/// Prints an expression as an indented tree, with the line and column
+/// each node starts at
+pub fn ast(ex: &ExprWL, format: DumpFormat) -> String {
+    match format {
+        DumpFormat::Text => {
+            let mut out = String::new();
+            write_tree(&mut out, ex, 0);
+            out
+        }
+        DumpFormat::Json => format!("{}\n", expr_json(ex)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    const SOURCE: &str = "## adds\nproc add(a: int, b) then\n    return a + b\nend\nlet s = \"{add(1, 2):>3} \\\"x\\\"\"\n";
+
+    fn lex(source: &str, format: DumpFormat) -> String {
+        tokens(
+            Lexer::new("<test>", source).lex().unwrap().into_iter(),
+            format,
+        )
+    }
+
+    fn parse(source: &str, format: DumpFormat) -> String {
+        let lexer = Lexer::new("<test>", source);
+        let program = Parser::new(lexer.tokens()).parse_program().unwrap();
+        ast(&program, format)
+    }
+
+    fn json(dump: &str) -> serde_json::Value {
+        serde_json::from_str(dump).unwrap()
+    }
+
+    #[test]
+    fn tokens_as_text() {
+        assert_eq!(
+            lex("let x: int = 1\n  x", DumpFormat::Text),
+            "1:1      Let\n\
+             1:5      Identifier([\"x\"])\n\
+             1:6      Colon\n\
+             1:8      BuiltinType(\"int\")\n\
+             1:12     OperatorSet\n\
+             1:14     IntegerLiteral(1)\n\
+             2:3      Identifier([\"x\"])\n\
+             2:4      EOF\n"
+        );
+    }
+
+    #[test]
+    fn ast_as_text() {
+        assert_eq!(
+            parse(SOURCE, DumpFormat::Text),
+            "Program @ 2:1\n\
+             \x20 Proc add(a: int, b) @ 2:1\n\
+             \x20   Return @ 3:5\n\
+             \x20     Binary Add @ 3:12\n\
+             \x20       Identifier a @ 3:12\n\
+             \x20       Identifier b @ 3:16\n\
+             \x20 Let s @ 5:1\n\
+             \x20   Interpolation @ 5:9\n\
+             \x20     Call add @ 5:11\n\
+             \x20       Literal Integer \"1\" @ 5:15\n\
+             \x20       Literal Integer \"2\" @ 5:18\n\
+             \x20     Text \" \\\"x\\\"\"\n"
+        );
+    }
+
+    #[test]
+    fn tokens_as_json() {
+        let tokens = json(&lex(
+            "x = \"tab\\t\\\"quote\\\" \\\\ \\x01\"",
+            DumpFormat::Json,
+        ));
+        assert_eq!(tokens[0]["kind"], "Identifier");
+        assert_eq!(tokens[0]["value"], "x");
+        assert_eq!(tokens[1]["value"], serde_json::Value::Null);
+        assert_eq!(tokens[2]["value"], "tab\t\"quote\" \\ \u{1}");
+        assert_eq!(
+            tokens[2]["span"],
+            serde_json::json!({"file": "<test>", "line": 1, "column": 5, "start": 4, "end": 28})
+        );
+        assert_eq!(tokens[3]["kind"], "EOF");
+
+        let tokens = json(&lex("\"{n:>3}\" 7n 2.5", DumpFormat::Json));
+        let parts = &tokens[0]["value"];
+        assert_eq!(parts[0]["tokens"][0]["value"], "n");
+        assert_eq!(parts[0]["spec"], ">3");
+        assert_eq!(tokens[1]["value"], "7");
+        assert_eq!(tokens[2]["value"], 2.5);
+    }
+
+    #[test]
+    fn ast_as_json() {
+        let program = json(&parse(SOURCE, DumpFormat::Json));
+        assert_eq!(program["kind"], "Program");
+        let proc = &program["body"][0];
+        assert_eq!(proc["kind"], "Proc");
+        assert_eq!(proc["doc"], "adds");
+        assert_eq!(
+            proc["params"],
+            serde_json::json!([{"name": "a", "type": "int"}, {"name": "b", "type": null}])
+        );
+        assert_eq!(proc["returns"], serde_json::Value::Null);
+        assert_eq!(proc["body"][0]["value"]["op"], "Add");
+        assert_eq!(proc["span"]["line"], 2);
+
+        let parts = &program["body"][1]["value"]["parts"];
+        assert_eq!(parts[0]["value"]["kind"], "Call");
+        assert_eq!(
+            parts[0]["spec"],
+            serde_json::json!({"fill": " ", "align": "right", "zero": false, "width": 3, "precision": null})
+        );
+        assert_eq!(parts[1]["text"], " \"x\"");
+    }
+
+    #[test]
+    fn format_names() {
+        assert_eq!(DumpFormat::from_name("text"), Some(DumpFormat::Text));
+        assert_eq!(DumpFormat::from_name("json"), Some(DumpFormat::Json));
+        assert_eq!(DumpFormat::from_name("yaml"), None);
+    }
+}
@@ -0,0 +1,149 @@
+use std::fs;
+use std::path::Path;
+
+use crate::error::{Error, ErrorKind};
+use crate::interpreter::Interpreter;
+use crate::io::Io;
+use crate::lexer::Lexer;
+use crate::limits::{InterruptHandle, Limits};
+use crate::native::{NativeFn, NativeModule, NativeProc};
+use crate::parser::{Expr, ExprWL, Parser};
+use crate::source::{SourceFile, Span};
+use crate::value::Value;
+
+/// Runs Bull Script from Rust. Everything a script declares stays declared
+/// between calls, so procs from one `eval` can be called from the next.
+pub struct Engine {
+    interpreter: Interpreter,
+    /// The name last given to `call`, as a file its errors can point into,
+    /// so calling the same proc again doesn't make another
+    call_file: SourceFile,
+}
+
+/// A name from Rust, like `ns.proc`, split into the parts of its path
+fn path(name: &str) -> Vec<String> {
+    name.split('.').map(|part| part.to_string()).collect()
+}
+
+fn parse(name: &str, source: &str, limits: &Limits) -> Result<Vec<ExprWL>, Error> {
+    let lexer = Lexer::new(name, source);
+    let program = Parser::new(lexer.tokens())
+        .with_max_stack(limits.max_stack)
+        .parse_program()?;
+    match program.expr {
+        Expr::Program(code) => Ok(code),
+        _ => unreachable!("Parsing a program always gives a program"),
+    }
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Self {
+            interpreter: Interpreter::new(),
+            call_file: SourceFile::new("<call>", ""),
+        }
+    }
+
+    /// Sends everything scripts print, and takes everything they read,
+    /// through `io`, such as a `BufferIo` to capture their output
+    pub fn with_io(mut self, io: impl Io + 'static) -> Self {
+        self.interpreter = self.interpreter.with_io(io);
+        self
+    }
+
+    /// Stops scripts that go over `limits`, with an error whose `kind` says
+    /// which. Each `eval`, `run_file` and `call` gets the whole of them.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.interpreter = self.interpreter.with_limits(limits);
+        self
+    }
+
+    /// A handle another thread can stop the running script with
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interpreter.interrupt_handle()
+    }
+
+    /// Runs some code at the top level, returning the value of the last
+    /// statement if it is an expression, or null otherwise
+    pub fn eval(&mut self, source: &str) -> Result<Value, Error> {
+        let code = parse("<eval>", source, self.interpreter.limits())?;
+        self.interpreter.restart_limits();
+        let mut last = Value::Null;
+        for ex in &code {
+            last = self.interpreter.run_statement(ex)?.unwrap_or(Value::Null);
+        }
+        Ok(last)
+    }
+
+    /// Runs a script the way `bull-script run` does, declaring everything in
+    /// it and then calling its `main` if it has one
+    pub fn run_file(&mut self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        let name = path.display().to_string();
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text.replace('\r', ""),
+            Err(e) => {
+                return Err(Error {
+                    span: Span::new(SourceFile::named(&name), 0, 0),
+                    reason: format!("Could not read {}: {}", name, e),
+                    kind: ErrorKind::Script,
+                })
+            }
+        };
+        self.interpreter.restart_limits();
+        let lexer = Lexer::new(&name, &text);
+        let program = Parser::new(lexer.tokens())
+            .with_max_stack(self.interpreter.limits().max_stack)
+            .parse_program()?;
+        self.interpreter.run_program(program)
+    }
+
+    /// Calls a proc by its full name, like `ns.proc`
+    pub fn call(&mut self, name: &str, args: Vec<Value>) -> Result<Value, Error> {
+        // errors point at the name, as if it had been written in a script
+        if *self.call_file.text() != *name {
+            self.call_file = SourceFile::new("<call>", name);
+        }
+        let ex = ExprWL {
+            expr: Expr::Identifier(path(name)),
+            span: Span::new(self.call_file.clone(), 0, name.len()),
+            doc: None,
+        };
+        self.interpreter.restart_limits();
+        self.interpreter.call_named(&ex, &path(name), args)
+    }
+
+    /// Declares a Rust function as a proc scripts can call by `name`, like
+    /// `host.greet`. Its arguments are converted with `FromValue` and checked
+    /// first, and its result is converted with `IntoValue`.
+    pub fn register_fn<Args>(&mut self, name: &str, f: impl NativeFn<Args> + 'static) {
+        self.interpreter.register_fn(name, f);
+    }
+
+    /// Declares every proc of a native module in its namespace
+    pub fn register_module(&mut self, module: NativeModule) {
+        self.interpreter.register_module(module);
+    }
+
+    /// Declares a native proc by its full name, such as one made by
+    /// `#[bull_fn]`
+    pub fn register_native(&mut self, native: NativeProc) {
+        self.interpreter.register_native(native);
+    }
+
+    /// The value of a global variable, constant or proc by its full name
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.interpreter.global(&path(name)).cloned()
+    }
+
+    /// Sets a global by its full name, declaring it if scripts haven't
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        self.interpreter.set_global(path(name), value);
+    }
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
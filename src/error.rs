@@ -1,10 +1,95 @@
-use std::process;
+use std::fmt::Display;
 
-use crate::interpreter::Stack;
+use crate::source::Span;
 
-pub fn error_at(filen: &String, linen: &i32, charn: &i32, reason: &String) -> ! {
-    panic!(
-        "\x1b[31;1m[ERROR]\x1b[0m: At {}:{}:{}:\n\t{}",
-        filen, linen, charn, reason
-    );
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error {
+    pub span: Span,
+    pub reason: String,
+    pub kind: ErrorKind,
+}
+
+/// What raised an error, so a host can tell a script that went over one of
+/// its `Limits` apart from one that failed on its own
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The script is invalid, or raised an error while running
+    Script,
+    /// The script ran more steps than it was allowed
+    StepLimit,
+    /// The script ran for longer than it was allowed
+    Timeout,
+    /// Calls were nested deeper than allowed, usually by endless recursion,
+    /// or calls or code were nested deeper than the stack allows
+    CallDepth,
+    /// The script's strings, lists and maps grew bigger than allowed
+    HeapLimit,
+    /// The host stopped the script with an `InterruptHandle`
+    Interrupted,
+}
+
+impl ErrorKind {
+    /// Whether the script was stopped rather than failing, which scripts
+    /// can't catch with `assert_throws`
+    pub fn is_limit(self) -> bool {
+        self != ErrorKind::Script
+    }
+}
+
+/// Shows the error with the line it happened on. The alternate form, `{:#}`,
+/// colours it for a terminal.
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = if f.alternate() {
+            "\x1b[31;1m[ERROR]\x1b[0m"
+        } else {
+            "[ERROR]"
+        };
+        write!(f, "{}: At {}:\n\t{}\n", label, self.span, self.reason)?;
+        write_snippet(f, &self.span)
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Writes the line `span` starts on, underlining the span up to the end
+/// of that line. Nothing is written if the file's text isn't known.
+pub(crate) fn write_snippet(f: &mut std::fmt::Formatter<'_>, span: &Span) -> std::fmt::Result {
+    let (Some((line, column)), Some(text)) = (span.line_col(), span.line_text()) else {
+        return Ok(());
+    };
+    let before: String = text.chars().take(column - 1).collect();
+    let width = span
+        .text()
+        .unwrap_or_default()
+        .lines()
+        .next()
+        .unwrap_or("")
+        .chars()
+        .count();
+    let width = width.min(text.chars().count() + 1 - column).max(1);
+    let gutter = " ".repeat(line.to_string().len());
+    write!(
+        f,
+        "{} |\n{} | {}\n{} | {}{}",
+        gutter,
+        line,
+        text,
+        gutter,
+        // keep tabs so the underline lines up with the text above it
+        before
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>(),
+        "^".repeat(width)
+    )
+}
+
+/// A front end error, found while lexing or parsing
+pub(crate) fn error_at(span: Span, reason: &str) -> Error {
+    Error {
+        span,
+        reason: reason.to_string(),
+        kind: ErrorKind::Script,
+    }
 }
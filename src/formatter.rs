@@ -0,0 +1,425 @@
+use std::sync::Arc;
+
+use crate::cst::{Cst, CstToken};
+use crate::error::Error;
+use crate::lexer::{Token, Trivia, TriviaKind};
+use crate::limits::DEFAULT_MAX_STACK;
+use crate::parser::{BinaryOperator, Expr, ExprWL, Type, UnaryOperator};
+use crate::source::Span;
+
+const INDENT: &str = "    ";
+
+/// Pretty-prints a program with canonical indentation and spacing.
+///
+/// The code is printed from the AST, and the comments are taken from the
+/// CST as the statements they sit between are written. Comments inside
+/// a statement are moved onto their own lines above it, apart from the
+/// one at the end of its last line.
+pub struct Formatter<'a> {
+    cst: &'a Cst,
+    source: Arc<str>,
+    /// The first token that hasn't been written yet
+    cursor: usize,
+    indent: usize,
+    /// A blank line was found before the next line
+    blank: bool,
+    /// Nothing has been written in the current block yet, so blank lines
+    /// are dropped
+    block_start: bool,
+    /// The byte ranges of blocks in the statement being written that are
+    /// used as values, which are kept as written along with their comments
+    raw: Vec<(usize, usize)>,
+    /// How much stack parsing the code may use
+    max_stack: Option<usize>,
+    out: String,
+}
+
+impl<'a> Formatter<'a> {
+    pub fn new(cst: &'a Cst) -> Self {
+        Formatter {
+            cst,
+            source: cst.file.text(),
+            cursor: 0,
+            indent: 0,
+            blank: false,
+            block_start: true,
+            raw: vec![],
+            max_stack: Some(DEFAULT_MAX_STACK),
+            out: String::new(),
+        }
+    }
+
+    /// Lets the code be nested as deep as it can be to run it with the
+    /// same `Limits::with_max_stack`
+    pub fn with_max_stack(mut self, bytes: Option<usize>) -> Self {
+        self.max_stack = bytes;
+        self
+    }
+
+    pub fn format(mut self) -> Result<String, Error> {
+        let program = self
+            .cst
+            .parser()
+            .with_max_stack(self.max_stack)
+            .parse_program()?;
+        if let Expr::Program(code) = &program.expr {
+            for statement in code {
+                self.statement(statement);
+            }
+        }
+        // comments at the end of the file lead the EOF token
+        let tokens = &self.cst.tokens[self.cursor..];
+        for token in tokens {
+            self.leading(&token.leading);
+        }
+        let len = self.out.trim_end().len();
+        self.out.truncate(len);
+        if !self.out.is_empty() {
+            self.out.push('\n');
+        }
+        Ok(self.out)
+    }
+
+    fn statement(&mut self, statement: &ExprWL) {
+        self.raw.clear();
+        match &statement.expr {
+            Expr::Proc(name, params, ret, body) => {
+                let params: Vec<String> = params
+                    .iter()
+                    .map(|(param, t)| match t {
+                        Some(t) => format!("{}: {}", param, t),
+                        None => param.clone(),
+                    })
+                    .collect();
+                let ret = ret.as_ref().map_or(String::new(), |t| format!(": {}", t));
+                let header = format!("proc {}({}){} then", name.join("."), params.join(", "), ret);
+                self.block(statement, header, body);
+            }
+            Expr::If(condition, body) => {
+                let header = format!("if {} then", self.expr(condition));
+                self.block(statement, header, body);
+            }
+            Expr::For(var, start, end, body) => {
+                let header = format!(
+                    "for {} = {} to {} then",
+                    var.join("."),
+                    self.expr(start),
+                    self.expr(end)
+                );
+                self.block(statement, header, body);
+            }
+            Expr::While(condition, body) => {
+                let header = format!("while {} then", self.expr(condition));
+                self.block(statement, header, body);
+            }
+            Expr::Namespace(name, body) => {
+                let header = format!("namespace {} then", name.join("."));
+                self.block(statement, header, body);
+            }
+            Expr::Test(_, body) => {
+                // the name is the token after `test`, kept as it was written
+                let tokens = &self.cst.tokens;
+                let i = tokens.partition_point(|t| t.token.span.start < statement.span.start);
+                let header = format!(
+                    "test {} then",
+                    self.token_text(tokens[i + 1].token.span.clone())
+                );
+                self.block(statement, header, body);
+            }
+            _ => {
+                let text = self.expr(statement);
+                let tokens = self.take_until(statement.span.end);
+                self.leading_of(tokens);
+                self.line(tokens, &text);
+            }
+        }
+    }
+
+    /// Writes a statement with a body, which ends with `end`
+    fn block(&mut self, statement: &ExprWL, header: String, body: &[ExprWL]) {
+        // the header runs up to the last `then` before the body
+        let body_start = body.first().map_or(statement.span.end, |s| s.span.start);
+        let header_len = self.cst.tokens[self.cursor..]
+            .iter()
+            .take_while(|t| t.token.span.start < body_start)
+            .count();
+        let then = self.cst.tokens[self.cursor..self.cursor + header_len]
+            .iter()
+            .rposition(|t| matches!(t.token.token, Token::Then() | Token::Colon()))
+            .map_or(self.cursor, |i| self.cursor + i);
+        let tokens = self.take_until(self.cst.tokens[then].token.span.end);
+        self.leading_of(tokens);
+        self.line(tokens, &header);
+
+        self.indent += 1;
+        self.block_start = true;
+        for statement in body {
+            self.statement(statement);
+        }
+        let tokens = self.take_until(statement.span.end);
+        // comments before `end` belong to the body
+        self.leading_of(tokens);
+        self.indent -= 1;
+        self.blank = false;
+        self.line(tokens, "end");
+    }
+
+    /// Takes the tokens from the cursor up to the one ending at `end`
+    fn take_until(&mut self, end: usize) -> &'a [CstToken] {
+        let cst = self.cst;
+        let start = self.cursor;
+        let len = cst.tokens[start..]
+            .iter()
+            .take_while(|t| t.token.span.end <= end && !matches!(t.token.token, Token::EOF()))
+            .count();
+        self.cursor += len;
+        &cst.tokens[start..start + len]
+    }
+
+    fn leading_of(&mut self, tokens: &[CstToken]) {
+        if let Some(first) = tokens.first() {
+            self.leading(&first.leading);
+        }
+    }
+
+    /// Writes a line made up of `tokens`, along with the comments
+    /// between them. The comments before the first token are written
+    /// separately, as they can be indented differently.
+    fn line(&mut self, tokens: &[CstToken], text: &str) {
+        let mut trailing = vec![];
+        for (i, token) in tokens.iter().enumerate() {
+            if i > 0 {
+                self.comments(&token.leading);
+            }
+            if i + 1 == tokens.len() {
+                trailing = self.comment_text(&token.trailing);
+            } else {
+                self.comments(&token.trailing);
+            }
+        }
+        self.start_line();
+        self.out.push_str(text);
+        for comment in trailing {
+            self.out.push(' ');
+            self.out.push_str(&comment);
+        }
+        self.out.push('\n');
+    }
+
+    /// Writes the comments before a line, keeping a blank line wherever
+    /// there were one or more
+    fn leading(&mut self, trivia: &[Trivia]) {
+        let mut newlines = 0;
+        for piece in trivia {
+            match piece.kind {
+                TriviaKind::Newline => newlines += 1,
+                TriviaKind::Whitespace => {}
+                _ => {
+                    self.blank |= newlines > 1;
+                    newlines = 0;
+                    self.start_line();
+                    self.out
+                        .push_str(&self.source[piece.span.start..piece.span.end]);
+                    self.out.push('\n');
+                }
+            }
+        }
+        self.blank |= newlines > 1;
+    }
+
+    /// Writes each comment on its own line
+    fn comments(&mut self, trivia: &[Trivia]) {
+        for comment in self.comment_text(trivia) {
+            self.start_line();
+            self.out.push_str(&comment);
+            self.out.push('\n');
+        }
+    }
+
+    /// The comments in `trivia`, leaving out those in blocks kept as written
+    fn comment_text(&self, trivia: &[Trivia]) -> Vec<String> {
+        trivia
+            .iter()
+            .filter(|t| !matches!(t.kind, TriviaKind::Newline | TriviaKind::Whitespace))
+            .filter(|t| {
+                !self
+                    .raw
+                    .iter()
+                    .any(|&(start, end)| start <= t.span.start && t.span.end <= end)
+            })
+            .map(|t| self.source[t.span.start..t.span.end].to_string())
+            .collect()
+    }
+
+    fn start_line(&mut self) {
+        if self.blank && !self.block_start {
+            self.out.push('\n');
+        }
+        self.blank = false;
+        self.block_start = false;
+        self.out.push_str(&INDENT.repeat(self.indent));
+    }
+
+    /// The source text of the token ending at `span`, which keeps
+    /// literals written the way they were
+    fn token_text(&self, span: Span) -> &str {
+        let tokens = &self.cst.tokens;
+        let i = tokens.partition_point(|t| t.token.span.end < span.end);
+        let span = tokens[i].token.span.clone();
+        &self.source[span.start..span.end]
+    }
+
+    fn expr(&mut self, e: &ExprWL) -> String {
+        match &e.expr {
+            Expr::Literal(..) | Expr::Interpolation(_) => {
+                self.token_text(e.span.clone()).to_string()
+            }
+            Expr::Group(inner) => format!("({})", self.expr(inner)),
+            Expr::Unary(op, right) => {
+                let op = match op {
+                    UnaryOperator::Negative => "-",
+                    UnaryOperator::LogicalNot => "not ",
+                };
+                format!("{}{}", op, self.expr(right))
+            }
+            Expr::Binary(op, left, right) => {
+                let op = match op {
+                    BinaryOperator::Add => "+",
+                    BinaryOperator::Subtract => "-",
+                    BinaryOperator::Multiply => "*",
+                    BinaryOperator::Divide => "/",
+                    BinaryOperator::Mod => "%",
+                    BinaryOperator::Equal => "is",
+                    BinaryOperator::NotEqual => "isnt",
+                    BinaryOperator::Lesser => "<",
+                    BinaryOperator::Greater => ">",
+                    BinaryOperator::LesserEqual => "<=",
+                    BinaryOperator::GreaterEqual => ">=",
+                };
+                format!("{} {} {}", self.expr(left), op, self.expr(right))
+            }
+            Expr::Identifier(name) => name.join("."),
+            Expr::Call(name, args) => {
+                let args: Vec<String> = args.iter().map(|a| self.expr(a)).collect();
+                format!("{}({})", name.join("."), args.join(", "))
+            }
+            Expr::Import(true, _) => format!("import {}", self.token_text(e.span.clone())),
+            Expr::Import(false, path) => format!("import {}", path),
+            Expr::Return(value) => format!("return {}", self.expr(value)),
+            Expr::VariableDeclaration(name, t, value) => {
+                format!(
+                    "let {}{} = {}",
+                    name.join("."),
+                    annotation(t),
+                    self.expr(value)
+                )
+            }
+            Expr::ConstantDeclaration(name, t, value) => {
+                format!(
+                    "const {}{} = {}",
+                    name.join("."),
+                    annotation(t),
+                    self.expr(value)
+                )
+            }
+            Expr::VariableSet(name, value) => format!("{} = {}", name.join("."), self.expr(value)),
+            // blocks are formatted as statements, so one used as a value is
+            // kept as it was written
+            Expr::Program(_)
+            | Expr::Proc(..)
+            | Expr::If(..)
+            | Expr::For(..)
+            | Expr::While(..)
+            | Expr::Namespace(..)
+            | Expr::Test(..) => {
+                self.raw.push((e.span.start, e.span.end));
+                self.source[e.span.start..e.span.end].to_string()
+            }
+        }
+    }
+}
+
+fn annotation(t: &Option<Type>) -> String {
+    t.as_ref().map_or(String::new(), |t| format!(": {}", t))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dump::{self, DumpFormat};
+    use crate::lexer::Lexer;
+
+    fn fmt(source: &str) -> String {
+        let lexer = Lexer::new("test.bs", source);
+        Formatter::new(&Cst::new(&lexer).unwrap()).format().unwrap()
+    }
+
+    /// The AST as a tree without the positions of its nodes, which
+    /// formatting moves
+    fn ast(source: &str) -> String {
+        let program = Cst::new(&Lexer::new("test.bs", source))
+            .unwrap()
+            .to_ast()
+            .unwrap();
+        dump::ast(&program, DumpFormat::Text)
+            .lines()
+            .map(|line| line.rsplit_once(" @ ").map_or(line, |(node, _)| node))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Formatting keeps the program the same, and formatting it again
+    /// changes nothing
+    fn assert_formats(source: &str) -> String {
+        let formatted = fmt(source);
+        assert_eq!(ast(&formatted), ast(source), "formatted:\n{}", formatted);
+        assert_eq!(fmt(&formatted), formatted);
+        formatted
+    }
+
+    #[test]
+    fn layout() {
+        let formatted = assert_formats(
+            "proc add(a:int,b: int):int :\n  return a+b\nend\n\n\n\nif add(1,2) is 3 then\nlet x=1\n  end",
+        );
+        assert_eq!(
+            formatted,
+            "proc add(a: int, b: int): int then\n    return a + b\nend\n\nif add(1, 2) is 3 then\n    let x = 1\nend\n"
+        );
+    }
+
+    #[test]
+    fn comments() {
+        let formatted = assert_formats(
+            "# leading\nlet x = 1 # trailing\nlet y = (x + # inside\n 2)\n#[ block\n   comment ]#\nproc f() then\n    # in the body\nend\n# at the end",
+        );
+        assert_eq!(
+            formatted,
+            "# leading\nlet x = 1 # trailing\n# inside\nlet y = (x + 2)\n#[ block\n   comment ]#\nproc f() then\n    # in the body\nend\n# at the end\n"
+        );
+    }
+
+    #[test]
+    fn comments_in_nested_procs() {
+        let source = "import std\nstd.assert_throws(proc boom() then\n    # explodes\n    return 1 / 0 # always\nend, \"zero\") # trailing\n";
+        assert_eq!(assert_formats(source), source);
+        let nested = "namespace ns then\n  proc outer() then\n    # outer\n    std.assert_throws(proc inner() then\n        # inner\n        return 1\n    end, \"x\")\n  end\nend\n";
+        let formatted = assert_formats(nested);
+        assert_eq!(formatted.matches("# inner").count(), 1);
+        assert_eq!(formatted.matches("# outer").count(), 1);
+    }
+
+    #[test]
+    fn scripts_in_the_repo() {
+        let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+        for dir in ["tests", "examples", "src/lib"] {
+            for entry in std::fs::read_dir(root.join(dir)).unwrap() {
+                let path = entry.unwrap().path();
+                if path.extension().is_some_and(|ext| ext == "bs") {
+                    let source = std::fs::read_to_string(&path).unwrap();
+                    assert_formats(&source.replace('\r', ""));
+                }
+            }
+        }
+    }
+}
@@ -1,51 +1,1099 @@
 use std::collections::HashMap;
+use std::rc::Rc;
 
+use crate::bigint::BigInt;
 use crate::chainmap::ChainMap;
-use crate::error::error_at;
-use crate::parser::{Expr, ExprWL};
+use crate::error::{Error, ErrorKind};
+use crate::io::{Io, ProcessIo};
+use crate::lexer::Lexer;
+use crate::limits::{Budget, InterruptHandle, Limits};
+use crate::native::{NativeFn, NativeModule, NativeProc};
+use crate::parser::{
+    BinaryOperator, Expr, ExprWL, InterpolationPart, LiteralType, Parser, Type, UnaryOperator,
+};
+use crate::testing::TestCase;
+use crate::value::{Procedure, Value};
 
-pub struct Interpreter {
-    stack: Stack,
+pub(crate) const LIBRARIES: [(&str, &str); 2] = [
+    ("std", include_str!("lib/std.bs")),
+    ("math", include_str!("lib/math.bs")),
+];
+
+const BUILTINS: [&str; 10] = [
+    "printval",
+    "printstr",
+    "eprintstr",
+    "readline",
+    "pow",
+    "div",
+    "args",
+    "assert",
+    "assert_eq",
+    "assert_throws",
+];
+
+/// Builtins named after the type they convert to, called as `int(x)`
+const CONVERSIONS: [&str; 5] = ["int", "float", "bigint", "str", "bool"];
+
+#[derive(Debug, Clone)]
+struct Variable {
+    value: Value,
+    ty: Option<Type>,
+    constant: bool,
 }
 
-fn unbox<T>(value: Box<T>) -> T {
-    *value
+/// The locals of a single proc call
+struct Frame {
+    scopes: ChainMap<String, Variable>,
+    namespace: Vec<String>,
+}
+
+pub struct Interpreter {
+    globals: HashMap<Vec<String>, Variable>,
+    frames: Vec<Frame>,
+    /// The namespace currently being declared at the top level
+    namespace: Vec<String>,
+    imported: Vec<String>,
+    /// The arguments passed to the script after its file name
+    args: Vec<String>,
+    io: Box<dyn Io>,
+    budget: Budget,
 }
 
 macro_rules! error {
     ($loc: expr, $reason: expr) => {
-        error_at(&$loc.filen, &$loc.linen, &$loc.charn, &$reason);
+        return Err(Error {
+            span: $loc.span.clone(),
+            reason: $reason,
+            kind: ErrorKind::Script,
+        })
     };
 }
 
-pub(crate) type Stack = Vec<HashMap<Vec<String>, ExprWL>>;
-
 impl Interpreter {
-    fn run_code(&mut self, code: Vec<ExprWL>) {
-        let mut scope = HashMap::new();
-        self.stack.push(scope);
+    /// Runs a list of statements in a new scope, returning the value of
+    /// a `return` if one was hit
+    fn run_code(&mut self, code: &[ExprWL]) -> Result<Option<Value>, Error> {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.scopes.push_hash();
+        }
+        let mut ret = None;
         for ex in code {
-            let expr = ex.clone().expr;
+            ret = self.execute(ex)?;
+            if ret.is_some() {
+                break;
+            }
+        }
+        if let Some(frame) = self.frames.last_mut() {
+            frame.scopes.pop_hash();
+        }
+        Ok(ret)
+    }
+
+    fn execute(&mut self, ex: &ExprWL) -> Result<Option<Value>, Error> {
+        self.step(ex)?;
+        match &ex.expr {
+            Expr::VariableDeclaration(name, ty, value) => {
+                let value = self.evaluate(value)?;
+                self.declare(ex, name, ty, value, false)?;
+            }
+            Expr::ConstantDeclaration(name, ty, value) => {
+                let value = self.evaluate(value)?;
+                self.declare(ex, name, ty, value, true)?;
+            }
+            Expr::Import(relative, path) => {
+                if *relative {
+                    error!(ex, "Relative imports are not implemented yet".to_string());
+                }
+                self.import(ex, path)?;
+            }
+            Expr::Namespace(name, body) => {
+                if !self.frames.is_empty() {
+                    error!(
+                        ex,
+                        "Namespaces can only be declared at the top level".to_string()
+                    );
+                }
+                let depth = self.namespace.len();
+                self.namespace.extend(name.iter().cloned());
+                for ex in body {
+                    self.execute(ex)?;
+                }
+                self.namespace.truncate(depth);
+            }
+            Expr::If(cond, body) => {
+                if self.evaluate(cond)?.is_truthy() {
+                    return self.run_code(body);
+                }
+            }
+            Expr::For(name, start, end, body) => {
+                let startval = self.evaluate(start)?;
+                let endval = self.evaluate(end)?;
+                // the counter is a float if either bound is, and otherwise an
+                // int or bigint that is compared and counted exactly
+                let mut i = match (&startval, &endval) {
+                    (Value::Float(_), _) | (_, Value::Float(_))
+                        if startval.as_float().is_some() && endval.as_float().is_some() =>
+                    {
+                        Value::Float(startval.as_float().unwrap())
+                    }
+                    (
+                        Value::Integer(_) | Value::BigInt(_),
+                        Value::Integer(_) | Value::BigInt(_),
+                    ) => startval,
+                    (Value::Integer(_) | Value::BigInt(_) | Value::Float(_), v) => {
+                        error!(end, format!("Expected a number, got {}", v.type_name()))
+                    }
+                    (v, _) => error!(start, format!("Expected a number, got {}", v.type_name())),
+                };
+                while self
+                    .binary(ex, &BinaryOperator::Lesser, i.clone(), endval.clone())?
+                    .is_truthy()
+                {
+                    // an empty loop runs nothing else that counts as a step
+                    self.step(ex)?;
+                    if let Some(frame) = self.frames.last_mut() {
+                        frame.scopes.push_hash();
+                    }
+                    self.declare(ex, name, &None, i.clone(), false)?;
+                    let ret = self.run_code(body)?;
+                    if let Some(frame) = self.frames.last_mut() {
+                        frame.scopes.pop_hash();
+                    }
+                    if ret.is_some() {
+                        return Ok(ret);
+                    }
+                    i = self.binary(ex, &BinaryOperator::Add, i, Value::Integer(1))?;
+                }
+            }
+            Expr::While(cond, body) => {
+                while self.evaluate(cond)?.is_truthy() {
+                    let ret = self.run_code(body)?;
+                    if ret.is_some() {
+                        return Ok(ret);
+                    }
+                }
+            }
+            Expr::Return(value) => {
+                return Ok(Some(self.evaluate(value)?));
+            }
+            // tests are only run by `run_test`
+            Expr::Test(..) => {}
+            _ => {
+                self.evaluate(ex)?;
+            }
+        }
+        Ok(None)
+    }
+
+    fn evaluate(&mut self, ex: &ExprWL) -> Result<Value, Error> {
+        self.step(ex)?;
+        match &ex.expr {
+            Expr::Literal(LiteralType::String, s) => Ok(Value::String(s.clone())),
+            Expr::Literal(LiteralType::Integer, n) => match n.parse::<i64>() {
+                Ok(n) => Ok(Value::Integer(n)),
+                Err(_) => error!(ex, format!("Invalid integer literal {}", n)),
+            },
+            Expr::Literal(LiteralType::Float, n) => match n.parse::<f64>() {
+                Ok(n) => Ok(Value::Float(n)),
+                Err(_) => error!(ex, format!("Invalid float literal {}", n)),
+            },
+            Expr::Literal(LiteralType::BigInt, n) => match BigInt::parse(n) {
+                Some(n) => Ok(Value::BigInt(n)),
+                None => error!(ex, format!("Invalid bigint literal {}", n)),
+            },
+            Expr::Literal(LiteralType::Boolean, b) => Ok(Value::Boolean(b == "1")),
+            Expr::Literal(LiteralType::Null, _) => Ok(Value::Null),
+            Expr::Group(inner) => self.evaluate(inner),
+            Expr::Unary(op, right) => {
+                let right = self.evaluate(right)?;
+                match (op, right) {
+                    (UnaryOperator::Negative, Value::Integer(i)) => match i.checked_neg() {
+                        Some(i) => Ok(Value::Integer(i)),
+                        None => Ok(Value::BigInt(-&BigInt::from(i))),
+                    },
+                    (UnaryOperator::Negative, Value::BigInt(b)) => Ok(Value::BigInt(-&b)),
+                    (UnaryOperator::Negative, Value::Float(f)) => Ok(Value::Float(-f)),
+                    (UnaryOperator::LogicalNot, v) => Ok(Value::Boolean(!v.is_truthy())),
+                    (_, v) => error!(
+                        ex,
+                        format!("Cannot negate a value of type {}", v.type_name())
+                    ),
+                }
+            }
+            Expr::Binary(op, left, right) => {
+                let left = self.evaluate(left)?;
+                let right = self.evaluate(right)?;
+                let value = self.binary(ex, op, left, right)?;
+                self.allocate(ex, &value)?;
+                Ok(value)
+            }
+            Expr::Identifier(parts) => match self.lookup(parts) {
+                Some(value) => Ok(value),
+                None => error!(ex, format!("`{}` is not defined", parts.join("."))),
+            },
+            Expr::Call(parts, args) => {
+                let callee = match self.lookup(parts) {
+                    Some(callee) => callee,
+                    None => error!(ex, format!("`{}` is not defined", parts.join("."))),
+                };
+                let mut values = vec![];
+                for arg in args {
+                    values.push(self.evaluate(arg)?);
+                }
+                let value = self.call(ex, &callee, values)?;
+                self.allocate(ex, &value)?;
+                Ok(value)
+            }
+            Expr::Proc(name, params, ret, body) => {
+                let proc = Value::Proc(Rc::new(Procedure {
+                    name: name.clone(),
+                    params: params.clone(),
+                    ret: ret.clone(),
+                    body: body.clone(),
+                    namespace: self.current_namespace(),
+                    doc: ex.doc.clone(),
+                }));
+                self.declare(ex, name, &None, proc.clone(), true)?;
+                Ok(proc)
+            }
+            Expr::VariableSet(parts, value) => {
+                let value = self.evaluate(value)?;
+                self.assign(ex, parts, value.clone())?;
+                Ok(value)
+            }
+            Expr::Interpolation(parts) => {
+                let mut result = String::new();
+                for part in parts {
+                    match part {
+                        InterpolationPart::Text(text) => result.push_str(text),
+                        InterpolationPart::Value(value, spec) => {
+                            let value = self.evaluate(value)?;
+                            match spec {
+                                Some(spec) => result.push_str(&value.format(spec)),
+                                None => result.push_str(&value.to_string()),
+                            }
+                        }
+                    }
+                }
+                let value = Value::String(result);
+                self.allocate(ex, &value)?;
+                Ok(value)
+            }
+            _ => error!(ex, format!("{:?} cannot be used as a value", ex.expr)),
+        }
+    }
+
+    /// Counts running `ex` against the limits
+    fn step(&mut self, ex: &ExprWL) -> Result<(), Error> {
+        self.budget.step().map_err(|e| e.at(ex.span.clone()))
+    }
+
+    /// Counts the heap a value that was just made takes up against the limit
+    fn allocate(&mut self, ex: &ExprWL, value: &Value) -> Result<(), Error> {
+        if self.budget.limits.max_heap.is_none() || self.budget.allocate(value.heap_size()) {
+            return Ok(());
+        }
+        let in_use = self.heap_in_use() + value.heap_size();
+        self.budget
+            .measure_heap(in_use)
+            .map_err(|e| e.at(ex.span.clone()))
+    }
+
+    /// The heap taken up by every variable, including the locals of every
+    /// call that is running
+    fn heap_in_use(&self) -> usize {
+        let globals = self.globals.values();
+        let locals = self.frames.iter().flat_map(|frame| frame.scopes.values());
+        globals.chain(locals).map(|var| var.value.heap_size()).sum()
+    }
 
-            if let Expr::VariableDeclaration(name, value) = expr.clone() {
-                scope.insert(name.clone(), unbox(value));
-            } else if let Expr::Import(relative, path) = expr.clone() {
-                if relative {
-                    error!(ex, format!("Relative imports are not implemented yet"));
-                } else {
+    fn binary(
+        &self,
+        ex: &ExprWL,
+        op: &BinaryOperator,
+        left: Value,
+        right: Value,
+    ) -> Result<Value, Error> {
+        let value = match (op, &left, &right) {
+            (BinaryOperator::Equal, l, r) => Value::Boolean(values_equal(l, r)),
+            (BinaryOperator::NotEqual, l, r) => Value::Boolean(!values_equal(l, r)),
+            // only strings are added to strings, as the checker expects
+            (BinaryOperator::Add, Value::String(l), Value::String(r)) => {
+                Value::String(format!("{}{}", l, r))
+            }
+            (_, Value::Integer(l), Value::Integer(r)) => match integer_binary(op, *l, *r) {
+                Ok(value) => value,
+                Err(reason) => error!(ex, reason),
+            },
+            (_, Value::Integer(_) | Value::BigInt(_), Value::Integer(_) | Value::BigInt(_)) => {
+                match bigint_binary(op, &left.as_bigint().unwrap(), &right.as_bigint().unwrap()) {
+                    Ok(value) => value,
+                    Err(reason) => error!(ex, reason),
+                }
+            }
+            (_, l, r) if l.as_float().is_some() && r.as_float().is_some() => {
+                float_binary(op, l.as_float().unwrap(), r.as_float().unwrap())
+            }
+            (BinaryOperator::Lesser, Value::String(l), Value::String(r)) => Value::Boolean(l < r),
+            (BinaryOperator::Greater, Value::String(l), Value::String(r)) => Value::Boolean(l > r),
+            (BinaryOperator::LesserEqual, Value::String(l), Value::String(r)) => {
+                Value::Boolean(l <= r)
+            }
+            (BinaryOperator::GreaterEqual, Value::String(l), Value::String(r)) => {
+                Value::Boolean(l >= r)
+            }
+            _ => error!(
+                ex,
+                format!(
+                    "Cannot use {:?} on {} and {}",
+                    op,
+                    left.type_name(),
+                    right.type_name()
+                )
+            ),
+        };
+        Ok(value)
+    }
+
+    fn call(&mut self, ex: &ExprWL, callee: &Value, args: Vec<Value>) -> Result<Value, Error> {
+        let proc = match callee {
+            Value::Proc(proc) => proc.clone(),
+            Value::Builtin(name) => return self.call_builtin(ex, name, args),
+            Value::Native(native) => {
+                if native.params.len() != args.len() {
+                    error!(
+                        ex,
+                        format!(
+                            "`{}` expects {} argument(s), got {}",
+                            native.name.join("."),
+                            native.params.len(),
+                            args.len()
+                        )
+                    );
+                }
+                return match native.call(args) {
+                    Ok(value) => Ok(value),
+                    Err(reason) => error!(ex, reason),
+                };
+            }
+            v => error!(
+                ex,
+                format!("A value of type {} is not callable", v.type_name())
+            ),
+        };
+        let name = proc.name.join(".");
+        if proc.params.len() != args.len() {
+            error!(
+                ex,
+                format!(
+                    "`{}` expects {} argument(s), got {}",
+                    name,
+                    proc.params.len(),
+                    args.len()
+                )
+            );
+        }
+
+        self.budget
+            .call(self.frames.len())
+            .map_err(|e| e.at(ex.span.clone()))?;
+
+        let mut scopes = ChainMap::new();
+        for ((param, ty), arg) in proc.params.iter().zip(args) {
+            if let Some(ty) = ty {
+                if !arg.is_of_type(ty) {
+                    error!(
+                        ex,
+                        format!(
+                            "Argument `{}` of `{}` expects {}, got {}",
+                            param,
+                            name,
+                            ty,
+                            arg.type_name()
+                        )
+                    );
+                }
+            }
+            scopes.insert(
+                param.clone(),
+                Variable {
+                    value: match ty {
+                        Some(ty) => arg.promote(ty),
+                        None => arg,
+                    },
+                    ty: ty.clone(),
+                    constant: false,
+                },
+            );
+        }
+
+        self.frames.push(Frame {
+            scopes,
+            namespace: proc.namespace.clone(),
+        });
+        let ret = self.run_code(&proc.body);
+        self.frames.pop();
+        let ret = ret?.unwrap_or(Value::Null);
+
+        if let Some(ty) = &proc.ret {
+            if !ret.is_of_type(ty) {
+                error!(
+                    ex,
+                    format!("`{}` should return {}, got {}", name, ty, ret.type_name())
+                );
+            }
+            return Ok(ret.promote(ty));
+        }
+        Ok(ret)
+    }
+
+    fn call_builtin(&mut self, ex: &ExprWL, name: &str, args: Vec<Value>) -> Result<Value, Error> {
+        match name {
+            _ if CONVERSIONS.contains(&name) => {
+                if args.len() != 1 {
+                    error!(
+                        ex,
+                        format!("`{}` expects 1 argument(s), got {}", name, args.len())
+                    );
+                }
+                match convert(name, args.into_iter().next().unwrap()) {
+                    Ok(value) => Ok(value),
+                    Err(reason) => error!(ex, reason),
+                }
+            }
+            "printval" | "printstr" => {
+                let text: Vec<String> = args.iter().map(|v| v.to_string()).collect();
+                self.io.write_out(&text.join(" "));
+                Ok(Value::Null)
+            }
+            "eprintstr" => {
+                let text: Vec<String> = args.iter().map(|v| v.to_string()).collect();
+                self.io.write_err(&text.join(" "));
+                Ok(Value::Null)
+            }
+            "readline" => Ok(match self.io.read_line() {
+                Some(line) => Value::String(line),
+                None => Value::Null,
+            }),
+            "assert" => {
+                if args.is_empty() || args.len() > 2 {
+                    error!(
+                        ex,
+                        format!("`assert` expects 1 or 2 argument(s), got {}", args.len())
+                    );
                 }
-            } else {
-                eprintln!("{:?} has not been implemented yet!", expr);
+                if !args[0].is_truthy() {
+                    let reason = match args.get(1) {
+                        Some(message) => message.to_string(),
+                        None => "Assertion failed".to_string(),
+                    };
+                    error!(ex, reason);
+                }
+                Ok(Value::Null)
+            }
+            "assert_eq" => {
+                if args.len() != 2 {
+                    error!(
+                        ex,
+                        format!("`assert_eq` expects 2 argument(s), got {}", args.len())
+                    );
+                }
+                if !values_equal(&args[0], &args[1]) {
+                    error!(
+                        ex,
+                        format!(
+                            "Assertion failed: {} isnt {}",
+                            quoted(&args[0]),
+                            quoted(&args[1])
+                        )
+                    );
+                }
+                Ok(Value::Null)
+            }
+            "assert_throws" => {
+                if args.is_empty() || args.len() > 2 {
+                    error!(
+                        ex,
+                        format!(
+                            "`assert_throws` expects 1 or 2 argument(s), got {}",
+                            args.len()
+                        )
+                    );
+                }
+                match (self.call(ex, &args[0], vec![]), args.get(1)) {
+                    (Ok(_), _) => error!(ex, "Expected an error, but none was raised".to_string()),
+                    // a script that went over its limits must stop
+                    (Err(e), _) if e.kind.is_limit() => Err(e),
+                    (Err(e), Some(expected)) if !e.reason.contains(&expected.to_string()) => {
+                        error!(
+                            ex,
+                            format!(
+                                "Expected an error with {}, got {}",
+                                quoted(expected),
+                                quoted(&Value::String(e.reason))
+                            )
+                        )
+                    }
+                    (Err(_), _) => Ok(Value::Null),
+                }
+            }
+            "pow" => {
+                if args.len() != 2 {
+                    error!(
+                        ex,
+                        format!("`pow` expects 2 argument(s), got {}", args.len())
+                    );
+                }
+                match power(&args[0], &args[1]) {
+                    Ok(value) => Ok(value),
+                    Err(reason) => error!(ex, reason),
+                }
+            }
+            "div" => {
+                if args.len() != 2 {
+                    error!(
+                        ex,
+                        format!("`div` expects 2 argument(s), got {}", args.len())
+                    );
+                }
+                match integer_division(&args[0], &args[1]) {
+                    Ok(value) => Ok(value),
+                    Err(reason) => error!(ex, reason),
+                }
+            }
+            "help" => {
+                if args.len() != 1 {
+                    error!(
+                        ex,
+                        format!("`help` expects 1 argument(s), got {}", args.len())
+                    );
+                }
+                match help(&args[0]) {
+                    Some(text) => {
+                        self.io.write_out(&format!("{}\n", text));
+                        Ok(Value::Null)
+                    }
+                    None => error!(
+                        ex,
+                        format!("`help` expects a proc, got {}", args[0].type_name())
+                    ),
+                }
+            }
+            "args" => Ok(Value::List(
+                self.args.iter().cloned().map(Value::String).collect(),
+            )),
+            _ => error!(ex, format!("Unknown builtin `{}`", name)),
+        }
+    }
+
+    fn import(&mut self, ex: &ExprWL, name: &String) -> Result<(), Error> {
+        if self.imported.contains(name) {
+            return Ok(());
+        }
+        let source = match LIBRARIES.iter().find(|(lib, _)| lib == name) {
+            Some((_, source)) => source,
+            None => error!(ex, format!("Unknown library `{}`", name)),
+        };
+        self.imported.push(name.clone());
+
+        let lexer = Lexer::new(&format!("<{}>", name), source);
+        let mut parser = Parser::new(lexer.tokens()).with_max_stack(self.budget.limits.max_stack);
+        let program = parser.parse_program()?;
+
+        // libraries are always declared at the top level
+        let frames = std::mem::take(&mut self.frames);
+        let namespace = std::mem::take(&mut self.namespace);
+        let result = self.run_top_level(&program);
+        self.frames = frames;
+        self.namespace = namespace;
+        result
+    }
+
+    fn run_top_level(&mut self, prog: &ExprWL) -> Result<(), Error> {
+        if let Expr::Program(code) = &prog.expr {
+            for ex in code {
+                self.execute(ex)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn current_namespace(&self) -> Vec<String> {
+        match self.frames.last() {
+            Some(frame) => frame.namespace.clone(),
+            None => self.namespace.clone(),
+        }
+    }
+
+    fn declare(
+        &mut self,
+        ex: &ExprWL,
+        name: &[String],
+        ty: &Option<Type>,
+        value: Value,
+        constant: bool,
+    ) -> Result<(), Error> {
+        if let Some(ty) = ty {
+            if !value.is_of_type(ty) {
+                error!(
+                    ex,
+                    format!(
+                        "`{}` is declared as {}, got {}",
+                        name.join("."),
+                        ty,
+                        value.type_name()
+                    )
+                );
+            }
+        }
+        let var = Variable {
+            value: match ty {
+                Some(ty) => value.promote(ty),
+                None => value,
+            },
+            ty: ty.clone(),
+            constant,
+        };
+        match self.frames.last_mut() {
+            Some(frame) => frame.scopes.insert(name.join("."), var),
+            None => {
+                let mut path = self.namespace.clone();
+                path.extend(name.iter().cloned());
+                self.globals.insert(path, var);
+            }
+        }
+        Ok(())
+    }
+
+    /// Resolves a global name, trying the current namespace and
+    /// each of its parents before the absolute path
+    fn resolve_global(&self, parts: &[String]) -> Option<Vec<String>> {
+        let namespace = self.current_namespace();
+        for i in (0..=namespace.len()).rev() {
+            let mut path = namespace[..i].to_vec();
+            path.extend(parts.iter().cloned());
+            if self.globals.contains_key(&path) {
+                return Some(path);
+            }
+        }
+        None
+    }
+
+    fn lookup(&self, parts: &[String]) -> Option<Value> {
+        if let Some(frame) = self.frames.last() {
+            if let Some(var) = frame.scopes.get(&parts.join(".")) {
+                return Some(var.value.clone());
+            }
+        }
+        if let Some(path) = self.resolve_global(parts) {
+            return Some(self.globals[&path].value.clone());
+        }
+        if parts.len() == 1 && CONVERSIONS.contains(&parts[0].as_str()) {
+            return Some(Value::Builtin(parts[0].clone()));
+        }
+        // `help` is global too, so it is quick to type in the REPL
+        if parts.len() == 1 && parts[0] == "help" {
+            return Some(Value::Builtin(parts[0].clone()));
+        }
+        if parts.len() == 2 && parts[0] == "builtin" && BUILTINS.contains(&parts[1].as_str()) {
+            return Some(Value::Builtin(parts[1].clone()));
+        }
+        None
+    }
+
+    fn assign(&mut self, ex: &ExprWL, parts: &[String], value: Value) -> Result<(), Error> {
+        let name = parts.join(".");
+        let global = self.resolve_global(parts);
+        let var = match self.frames.last_mut().and_then(|f| f.scopes.get_mut(&name)) {
+            Some(var) => var,
+            None => match global {
+                Some(path) => self.globals.get_mut(&path).unwrap(),
+                None => error!(ex, format!("`{}` is not defined", name)),
+            },
+        };
+        if var.constant {
+            error!(ex, format!("Cannot assign to constant `{}`", name));
+        }
+        if let Some(ty) = &var.ty {
+            if !value.is_of_type(ty) {
+                error!(
+                    ex,
+                    format!(
+                        "`{}` is declared as {}, got {}",
+                        name,
+                        ty,
+                        value.type_name()
+                    )
+                );
             }
         }
-        self.stack.pop();
+        var.value = match &var.ty {
+            Some(ty) => value.promote(ty),
+            None => value,
+        };
+        Ok(())
+    }
+
+    /// Declares everything in the program, then calls its `main` if it
+    /// declares one. A `main` left over from an earlier program isn't called.
+    pub fn run_program(&mut self, prog: ExprWL) -> Result<(), Error> {
+        self.run_top_level(&prog)?;
+        let declares_main = match &prog.expr {
+            Expr::Program(code) => code
+                .iter()
+                .any(|ex| matches!(&ex.expr, Expr::Proc(name, ..) if name == &["main"])),
+            _ => false,
+        };
+        if !declares_main {
+            return Ok(());
+        }
+        if let Some(main) = self.lookup(&["main".to_string()]) {
+            self.call(&prog, &main, vec![])?;
+        }
+        Ok(())
+    }
+
+    /// Runs a single statement at the top level, returning its value if
+    /// it is an expression rather than a declaration or block
+    pub fn run_statement(&mut self, ex: &ExprWL) -> Result<Option<Value>, Error> {
+        match &ex.expr {
+            Expr::VariableDeclaration(..)
+            | Expr::ConstantDeclaration(..)
+            | Expr::VariableSet(..)
+            | Expr::Import(..)
+            | Expr::Namespace(..)
+            | Expr::Proc(..)
+            | Expr::If(..)
+            | Expr::For(..)
+            | Expr::While(..)
+            | Expr::Return(..)
+            | Expr::Test(..) => {
+                self.execute(ex)?;
+                Ok(None)
+            }
+            _ => self.evaluate(ex).map(Some),
+        }
+    }
+
+    /// Calls a proc or builtin by name as if from the top level, reporting
+    /// errors at `ex`
+    pub fn call_named(
+        &mut self,
+        ex: &ExprWL,
+        parts: &[String],
+        args: Vec<Value>,
+    ) -> Result<Value, Error> {
+        match self.lookup(parts) {
+            Some(callee) => self.call(ex, &callee, args),
+            None => error!(ex, format!("`{}` is not defined", parts.join("."))),
+        }
+    }
+
+    /// The value of a global, by its full path
+    pub fn global(&self, path: &[String]) -> Option<&Value> {
+        self.globals.get(path).map(|var| &var.value)
+    }
+
+    /// Sets a global by its full path, replacing any variable or constant
+    /// already there with an untyped variable
+    pub fn set_global(&mut self, path: Vec<String>, value: Value) {
+        self.globals.insert(
+            path,
+            Variable {
+                value,
+                ty: None,
+                constant: false,
+            },
+        );
+    }
+
+    /// Declares a Rust function as a proc scripts can call by `name`, which
+    /// may be in a namespace like `host.greet`
+    pub fn register_fn<Args>(&mut self, name: &str, f: impl NativeFn<Args> + 'static) {
+        let path: Vec<String> = name.split('.').map(|part| part.to_string()).collect();
+        self.register_native(NativeProc::new(path, f));
+    }
+
+    /// Declares every proc of a native module in its namespace
+    pub fn register_module(&mut self, module: NativeModule) {
+        for native in module.into_procs() {
+            self.register_native(native);
+        }
+    }
+
+    /// Declares a native proc by its full name
+    pub fn register_native(&mut self, native: NativeProc) {
+        self.globals.insert(
+            native.name.clone(),
+            Variable {
+                value: Value::Native(Rc::new(native)),
+                ty: None,
+                constant: true,
+            },
+        );
     }
 
-    pub fn run_program(&mut self, prog: ExprWL) {
-        let expr = prog.clone().expr;
+    /// Declares everything in the program like `run_program` does, then
+    /// runs the body of one of its tests instead of `main`
+    pub fn run_test(&mut self, prog: &ExprWL, test: &TestCase) -> Result<(), Error> {
+        self.run_top_level(prog)?;
+        self.frames.push(Frame {
+            scopes: ChainMap::new(),
+            namespace: test.namespace.clone(),
+        });
+        let result = self.run_code(test.body);
+        self.frames.pop();
+        result.map(|_| ())
     }
 
     pub fn new() -> Self {
-        Self { stack: vec![] }
+        Self {
+            globals: HashMap::new(),
+            frames: vec![],
+            namespace: vec![],
+            imported: vec![],
+            args: vec![],
+            io: Box::new(ProcessIo),
+            budget: Budget::new(Limits::default()),
+        }
+    }
+
+    /// Sets the arguments the script gets from `std.args()`
+    pub fn with_args(mut self, args: Vec<String>) -> Self {
+        self.args = args;
+        self
+    }
+
+    /// Sends everything the script prints, and takes everything it reads,
+    /// through `io` instead of the process's stdout, stderr and stdin
+    pub fn with_io(mut self, io: impl Io + 'static) -> Self {
+        self.io = Box::new(io);
+        self
+    }
+
+    /// Stops scripts that go over `limits`, counting from now
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.budget.limits = limits;
+        self.budget.restart();
+        self
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.budget.limits
+    }
+
+    /// Starts counting steps, time and memory against the limits again, for
+    /// a new run
+    pub fn restart_limits(&mut self) {
+        self.budget.restart();
+    }
+
+    /// A handle another thread can stop the running script with
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.budget.interrupt_handle()
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The signature and doc comment of a proc, as `help` prints them
+fn help(value: &Value) -> Option<String> {
+    let (signature, doc) = match value {
+        Value::Proc(p) => {
+            let params: Vec<String> = p
+                .params
+                .iter()
+                .map(|(param, t)| match t {
+                    Some(t) => format!("{}: {}", param, t),
+                    None => param.clone(),
+                })
+                .collect();
+            let ret = p.ret.as_ref().map_or(String::new(), |t| format!(": {}", t));
+            let signature = format!("proc {}({}){}", p.name.join("."), params.join(", "), ret);
+            (signature, p.doc.clone())
+        }
+        Value::Native(p) => {
+            let params: Vec<String> = p.params.iter().map(|t| t.to_string()).collect();
+            let signature = format!("proc {}({})", p.name.join("."), params.join(", "));
+            (signature, p.doc.clone())
+        }
+        Value::Builtin(name) => (format!("builtin {}", name), None),
+        _ => return None,
+    };
+    Some(match doc {
+        Some(doc) => format!("{}\n{}", signature, doc),
+        None => signature,
+    })
+}
+
+/// A value as it would be written in a script, so strings can be told
+/// apart from other values in messages
+fn quoted(value: &Value) -> String {
+    match value {
+        Value::String(s) => format!("{:?}", s),
+        v => v.to_string(),
+    }
+}
+
+/// Compares two values, treating numbers of the same value as equal
+fn values_equal(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Float(_), r) if r.as_float().is_some() => left.as_float() == r.as_float(),
+        (l, Value::Float(_)) if l.as_float().is_some() => l.as_float() == right.as_float(),
+        (l, r) if l.as_bigint().is_some() && r.as_bigint().is_some() => {
+            l.as_bigint() == r.as_bigint()
+        }
+        (l, r) => l == r,
+    }
+}
+
+/// 64-bit integer arithmetic, promoting to a bigint on overflow.
+/// Dividing two ints always produces a float.
+fn integer_binary(op: &BinaryOperator, l: i64, r: i64) -> Result<Value, String> {
+    let result = match op {
+        BinaryOperator::Add => l.checked_add(r),
+        BinaryOperator::Subtract => l.checked_sub(r),
+        BinaryOperator::Multiply => l.checked_mul(r),
+        BinaryOperator::Divide if r == 0 => return Err("Division by zero".to_string()),
+        BinaryOperator::Divide => return Ok(Value::Float(l as f64 / r as f64)),
+        BinaryOperator::Mod if r == 0 => return Err("Division by zero".to_string()),
+        BinaryOperator::Mod => l.checked_rem(r),
+        BinaryOperator::Equal => return Ok(Value::Boolean(l == r)),
+        BinaryOperator::NotEqual => return Ok(Value::Boolean(l != r)),
+        BinaryOperator::Lesser => return Ok(Value::Boolean(l < r)),
+        BinaryOperator::Greater => return Ok(Value::Boolean(l > r)),
+        BinaryOperator::LesserEqual => return Ok(Value::Boolean(l <= r)),
+        BinaryOperator::GreaterEqual => return Ok(Value::Boolean(l >= r)),
+    };
+    match result {
+        Some(i) => Ok(Value::Integer(i)),
+        None => bigint_binary(op, &BigInt::from(l), &BigInt::from(r)),
     }
 }
+
+fn bigint_binary(op: &BinaryOperator, l: &BigInt, r: &BigInt) -> Result<Value, String> {
+    let value = match op {
+        BinaryOperator::Add => Value::BigInt(l + r),
+        BinaryOperator::Subtract => Value::BigInt(l - r),
+        BinaryOperator::Multiply => Value::BigInt(l * r),
+        BinaryOperator::Divide if r.is_zero() => return Err("Division by zero".to_string()),
+        // like ints, bigints divide into a float, which only keeps about 16
+        // digits, so `integer_division` is there for exact results
+        BinaryOperator::Divide => Value::Float(l.to_f64() / r.to_f64()),
+        BinaryOperator::Mod => match l.div_rem(r) {
+            Some((_, rem)) => Value::BigInt(rem),
+            None => return Err("Division by zero".to_string()),
+        },
+        BinaryOperator::Equal => Value::Boolean(l == r),
+        BinaryOperator::NotEqual => Value::Boolean(l != r),
+        BinaryOperator::Lesser => Value::Boolean(l < r),
+        BinaryOperator::Greater => Value::Boolean(l > r),
+        BinaryOperator::LesserEqual => Value::Boolean(l <= r),
+        BinaryOperator::GreaterEqual => Value::Boolean(l >= r),
+    };
+    Ok(value)
+}
+
+/// Raises `base` to `exponent`. Integer powers stay exact, promoting to a
+/// bigint when needed, and negative or fractional powers produce a float.
+fn power(base: &Value, exponent: &Value) -> Result<Value, String> {
+    match (base.as_bigint(), exponent) {
+        (Some(b), Value::Integer(e)) if *e >= 0 => {
+            let e = u32::try_from(*e).map_err(|_| format!("Exponent {} is too large", e))?;
+            let result = b.pow(e);
+            Ok(match (base, result.to_i64()) {
+                (Value::Integer(_), Some(i)) => Value::Integer(i),
+                _ => Value::BigInt(result),
+            })
+        }
+        _ => match (base.as_float(), exponent.as_float()) {
+            (Some(b), Some(e)) => Ok(Value::Float(b.powf(e))),
+            _ => Err(format!(
+                "Cannot raise {} to the power of {}",
+                base.type_name(),
+                exponent.type_name()
+            )),
+        },
+    }
+}
+
+/// Divides two ints or bigints exactly, rounding towards zero, for when
+/// the float `/` gives isn't precise enough
+fn integer_division(left: &Value, right: &Value) -> Result<Value, String> {
+    match (left, right) {
+        (Value::Integer(_), Value::Integer(0)) => Err("Division by zero".to_string()),
+        (Value::Integer(l), Value::Integer(r)) => Ok(match l.checked_div(*r) {
+            Some(i) => Value::Integer(i),
+            // only `i64::MIN / -1` overflows
+            None => Value::BigInt(-&BigInt::from(*l)),
+        }),
+        _ => match (left.as_bigint(), right.as_bigint()) {
+            (Some(l), Some(r)) => match l.div_rem(&r) {
+                Some((quotient, _)) => Ok(Value::BigInt(quotient)),
+                None => Err("Division by zero".to_string()),
+            },
+            _ => Err(format!(
+                "Cannot divide {} by {} exactly",
+                left.type_name(),
+                right.type_name()
+            )),
+        },
+    }
+}
+
+fn float_binary(op: &BinaryOperator, l: f64, r: f64) -> Value {
+    match op {
+        BinaryOperator::Add => Value::Float(l + r),
+        BinaryOperator::Subtract => Value::Float(l - r),
+        BinaryOperator::Multiply => Value::Float(l * r),
+        BinaryOperator::Divide => Value::Float(l / r),
+        BinaryOperator::Mod => Value::Float(l % r),
+        BinaryOperator::Equal => Value::Boolean(l == r),
+        BinaryOperator::NotEqual => Value::Boolean(l != r),
+        BinaryOperator::Lesser => Value::Boolean(l < r),
+        BinaryOperator::Greater => Value::Boolean(l > r),
+        BinaryOperator::LesserEqual => Value::Boolean(l <= r),
+        BinaryOperator::GreaterEqual => Value::Boolean(l >= r),
+    }
+}
+
+/// Converts a value with one of the `CONVERSIONS` builtins
+fn convert(name: &str, value: Value) -> Result<Value, String> {
+    let converted = match (name, &value) {
+        ("str", v) => Some(Value::String(v.to_string())),
+        ("bool", v) => Some(Value::Boolean(v.is_truthy())),
+        ("int", Value::Integer(_)) => Some(value.clone()),
+        // truncates towards zero, as long as the result fits
+        ("int", Value::Float(f)) if f.is_finite() && f.trunc().abs() < 9.2e18 => {
+            Some(Value::Integer(f.trunc() as i64))
+        }
+        ("int", Value::Boolean(b)) => Some(Value::Integer(*b as i64)),
+        ("int", Value::String(s)) => s.trim().parse::<i64>().ok().map(Value::Integer),
+        ("int", Value::BigInt(b)) => b.to_i64().map(Value::Integer),
+        ("bigint", Value::Integer(i)) => Some(Value::BigInt(BigInt::from(*i))),
+        ("bigint", Value::BigInt(_)) => Some(value.clone()),
+        ("bigint", Value::Float(f)) if f.is_finite() => {
+            BigInt::parse(&format!("{:.0}", f.trunc())).map(Value::BigInt)
+        }
+        ("bigint", Value::String(s)) => BigInt::parse(s.trim()).map(Value::BigInt),
+        ("float", Value::Integer(i)) => Some(Value::Float(*i as f64)),
+        ("float", Value::BigInt(b)) => Some(Value::Float(b.to_f64())),
+        ("float", Value::Float(_)) => Some(value.clone()),
+        ("float", Value::Boolean(b)) => Some(Value::Float(*b as i64 as f64)),
+        ("float", Value::String(s)) => s.trim().parse::<f64>().ok().map(Value::Float),
+        _ => None,
+    };
+    converted.ok_or_else(|| {
+        format!(
+            "Cannot convert {} `{}` to {}",
+            value.type_name(),
+            value,
+            name
+        )
+    })
+}
@@ -0,0 +1,102 @@
+use std::cell::RefCell;
+use std::io::{BufRead, Write};
+use std::rc::Rc;
+
+/// Where a script's output goes and where its input comes from. Every
+/// builtin that prints or reads goes through this, so hosts can capture it.
+pub trait Io {
+    fn write_out(&mut self, text: &str);
+    fn write_err(&mut self, text: &str);
+    /// Reads a line without its line ending, or gives `None` at the end of
+    /// the input
+    fn read_line(&mut self) -> Option<String>;
+}
+
+/// The process's own stdout, stderr and stdin
+pub struct ProcessIo;
+
+impl Io for ProcessIo {
+    fn write_out(&mut self, text: &str) {
+        let mut stdout = std::io::stdout();
+        _ = stdout.write_all(text.as_bytes());
+        _ = stdout.flush();
+    }
+
+    fn write_err(&mut self, text: &str) {
+        _ = std::io::stderr().write_all(text.as_bytes());
+    }
+
+    fn read_line(&mut self) -> Option<String> {
+        let mut line = String::new();
+        match std::io::stdin().lock().read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(strip_line_ending(line)),
+        }
+    }
+}
+
+fn strip_line_ending(mut line: String) -> String {
+    if line.ends_with('\n') {
+        line.pop();
+        if line.ends_with('\r') {
+            line.pop();
+        }
+    }
+    line
+}
+
+#[derive(Default)]
+struct Buffers {
+    out: String,
+    err: String,
+    input: String,
+}
+
+/// Keeps output in memory and reads input from a string. Clones share the
+/// same buffers, so a clone kept by the host sees what the script wrote.
+#[derive(Clone, Default)]
+pub struct BufferIo {
+    buffers: Rc<RefCell<Buffers>>,
+}
+
+impl BufferIo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gives the script `input` to read, and nothing after it
+    pub fn with_input(self, input: &str) -> Self {
+        self.buffers.borrow_mut().input = input.to_string();
+        self
+    }
+
+    /// Everything written to stdout so far
+    pub fn out(&self) -> String {
+        self.buffers.borrow().out.clone()
+    }
+
+    /// Everything written to stderr so far
+    pub fn err(&self) -> String {
+        self.buffers.borrow().err.clone()
+    }
+}
+
+impl Io for BufferIo {
+    fn write_out(&mut self, text: &str) {
+        self.buffers.borrow_mut().out.push_str(text);
+    }
+
+    fn write_err(&mut self, text: &str) {
+        self.buffers.borrow_mut().err.push_str(text);
+    }
+
+    fn read_line(&mut self) -> Option<String> {
+        let input = &mut self.buffers.borrow_mut().input;
+        if input.is_empty() {
+            return None;
+        }
+        let end = input.find('\n').map_or(input.len(), |i| i + 1);
+        let line: String = input.drain(..end).collect();
+        Some(strip_line_ending(line))
+    }
+}
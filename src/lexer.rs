@@ -1,15 +1,21 @@
 use core::fmt::Debug;
-use regex::Regex;
+use std::iter::Peekable;
+use std::ops::Range;
+use std::str::CharIndices;
+use std::sync::Arc;
 
-use crate::error::error_at;
+use crate::bigint::BigInt;
+use crate::error::{error_at, Error};
+use crate::source::{SourceFile, Span};
 
 pub struct Lexer {
-    pub text: String,
-    pub filename: String,
+    pub file: SourceFile,
+    pub text: Arc<str>,
 }
 
 #[derive(Debug, Clone)]
-pub enum Token<'a> {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Token {
     ImportKeyword(),
     On(),
     End(),
@@ -30,12 +36,18 @@ pub enum Token<'a> {
     LibraryPath(String),
     RelativePath(String),
     StringLiteral(String),
-    NumericLiteral(f64, String),
+    CharLiteral(char),
+    InterpolatedString(Vec<StringPart>),
+    IntegerLiteral(i64),
+    FloatLiteral(f64),
+    BigIntLiteral(String),
     BooleanLiteral(bool),
-    BuiltinType(&'a str),
+    NullLiteral(),
+    BuiltinType(String),
 
     Child(),
     Comma(),
+    Colon(),
     OpenParen(),
     CloseParen(),
     OpenSquare(),
@@ -61,35 +73,52 @@ pub enum Token<'a> {
     EOF(),
 }
 
+/// A piece of an interpolated string such as `"{n:>3} bottles"`
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StringPart {
+    Text(String),
+    /// The tokens of an embedded expression and its raw format specifier
+    Value(Vec<TWL>, Option<String>),
+}
+
 #[derive(Clone)]
-pub struct TWL<'a> {
-    pub token: Token<'a>,
-    pub charn: i32,
-    pub linen: i32,
-    pub filen: String,
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TWL {
+    pub token: Token,
+    pub span: Span,
+    /// The `##` doc comment written directly above the token
+    pub doc: Option<String>,
 }
 
-struct Loc {
-    charn: i32,
-    linen: i32,
-    filen: String,
+/// Source text that isn't part of any token
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TriviaKind {
+    /// Spaces and tabs, not including line breaks
+    Whitespace,
+    Newline,
+    LineComment,
+    BlockComment,
+    DocComment,
 }
 
-impl Debug for TWL<'_> {
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub span: Span,
+}
+
+impl Debug for TWL {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "At {}:{}:{} | {:?}",
-            self.filen, self.linen, self.charn, self.token
-        )
+        write!(f, "At {} | {:?}", self.span, self.token)
     }
 }
 
-struct Keyword<'a> {
-    str: &'a str,
-    token: Token<'a>,
+struct Keyword {
+    str: &'static str,
+    token: Token,
 }
-const KEYWORDS: [Keyword; 20] = [
+const KEYWORDS: [Keyword; 21] = [
     Keyword {
         str: "import",
         token: Token::ImportKeyword(),
@@ -166,12 +195,17 @@ const KEYWORDS: [Keyword; 20] = [
         str: "false",
         token: Token::BooleanLiteral(false),
     },
+    Keyword {
+        str: "null",
+        token: Token::NullLiteral(),
+    },
     Keyword {
         str: "return",
         token: Token::Return(),
     },
 ];
 
+// two character separators come first so they take precedence
 const SEPERATORS: [Keyword; 17] = [
     Keyword {
         str: "(",
@@ -219,15 +253,7 @@ const SEPERATORS: [Keyword; 17] = [
     },
     Keyword {
         str: ":",
-        token: Token::Then(),
-    },
-    Keyword {
-        str: "<",
-        token: Token::OperatorLesser(),
-    },
-    Keyword {
-        str: ">",
-        token: Token::OperatorGreater(),
+        token: Token::Colon(),
     },
     Keyword {
         str: "<=",
@@ -238,325 +264,990 @@ const SEPERATORS: [Keyword; 17] = [
         token: Token::OperatorGreaterEqual(),
     },
     Keyword {
-        str: ",",
-        token: Token::Comma(),
-    },
-];
-
-const BUILTIN_TYPES: [Keyword; 3] = [
-    Keyword {
-        str: "int",
-        token: Token::BuiltinType("int"),
+        str: "<",
+        token: Token::OperatorLesser(),
     },
     Keyword {
-        str: "str",
-        token: Token::BuiltinType("str"),
+        str: ">",
+        token: Token::OperatorGreater(),
     },
     Keyword {
-        str: "bool",
-        token: Token::BuiltinType("bool"),
+        str: ",",
+        token: Token::Comma(),
     },
 ];
 
+const BUILTIN_TYPES: [&str; 3] = ["int", "str", "bool"];
+
 impl Lexer {
-    pub fn lex(&self) -> Vec<TWL> {
-        let mut in_comment = false;
-        let mut in_string = false;
-        let mut in_escape = false;
-        let mut buffer = String::from("");
-        let mut tokens: Vec<TWL> = vec![];
-        let mut linen = 1;
-        let mut charn = 1;
-        let filen = self.filename.clone();
-
-        let add_token = |buffer: &mut String, tokens: &mut Vec<TWL>, linen: i32, charn: i32| {
-            if buffer == "" {
-                return;
-            }
-            let mut found = false;
-            for keyword_rule in KEYWORDS {
-                if buffer == keyword_rule.str {
-                    tokens.push(TWL {
-                        token: keyword_rule.token,
-                        charn: charn.clone(),
-                        linen: linen.clone(),
-                        filen: filen.clone(),
-                    });
-
-                    found = true;
-                    break;
+    /// Reads a file, ready to be lexed
+    pub fn new(filename: &str, text: &str) -> Self {
+        let file = SourceFile::new(filename, text);
+        Lexer {
+            text: file.text(),
+            file,
+        }
+    }
+
+    /// Lexes the text lazily, one token at a time
+    pub fn tokens(&self) -> Scanner<'_> {
+        Scanner::new(&self.text, self.file.clone())
+    }
+
+    pub fn lex(&self) -> Result<Vec<TWL>, Error> {
+        self.tokens().collect()
+    }
+}
+
+/// Scans source text one token at a time in a single pass, ending with
+/// an EOF token, or with the first error
+pub struct Scanner<'s> {
+    text: &'s str,
+    chars: Peekable<CharIndices<'s>>,
+    file: SourceFile,
+    /// Added to byte offsets, for text that starts partway into a file
+    offset: usize,
+    /// The doc comment waiting for the token it documents
+    doc: Option<String>,
+    /// The trivia skipped since it was last taken, if it is being kept
+    trivia: Option<Vec<Trivia>>,
+    done: bool,
+}
+
+impl Iterator for Scanner<'_> {
+    type Item = Result<TWL, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let token = self.next_token();
+        self.done = token
+            .as_ref()
+            .map_or(true, |token| matches!(token.token, Token::EOF()));
+        Some(token)
+    }
+}
+
+impl<'s> Scanner<'s> {
+    fn new(text: &'s str, file: SourceFile) -> Self {
+        Scanner::at(text, file, 0)
+    }
+
+    /// A scanner for text that starts at the given offset in a file,
+    /// such as an expression embedded in a string
+    fn at(text: &'s str, file: SourceFile, offset: usize) -> Self {
+        Scanner {
+            text,
+            chars: text.char_indices().peekable(),
+            file,
+            offset,
+            doc: None,
+            trivia: None,
+            done: false,
+        }
+    }
+
+    /// Keeps the whitespace and comments between tokens, to be taken
+    /// with `take_trivia`
+    pub fn with_trivia(mut self) -> Self {
+        self.trivia = Some(vec![]);
+        self
+    }
+
+    /// The trivia skipped since this was last called
+    pub fn take_trivia(&mut self) -> Vec<Trivia> {
+        self.trivia.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// The byte offset of the next character
+    fn pos(&mut self) -> usize {
+        self.chars.peek().map_or(self.text.len(), |(i, _)| *i)
+    }
+
+    /// The text from the next character on
+    fn rest(&mut self) -> &'s str {
+        let text = self.text;
+        &text[self.pos()..]
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().map(|(_, ch)| *ch)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        self.chars.next().map(|(_, ch)| ch)
+    }
+
+    /// Consumes `prefix` if the text continues with it
+    fn eat(&mut self, prefix: &str) -> bool {
+        if !self.rest().starts_with(prefix) {
+            return false;
+        }
+        for _ in prefix.chars() {
+            self.bump();
+        }
+        true
+    }
+
+    /// The span from `start` to the next character
+    fn span_from(&mut self, start: usize) -> Span {
+        Span::new(
+            self.file.clone(),
+            start + self.offset,
+            self.pos() + self.offset,
+        )
+    }
+
+    /// An error from `start` up to the next character
+    fn error(&mut self, start: usize, reason: &str) -> Error {
+        error_at(self.span_from(start), reason)
+    }
+
+    fn next_token(&mut self) -> Result<TWL, Error> {
+        self.skip_trivia()?;
+        let start = self.pos();
+        let token = match self.peek() {
+            Some(ch) => self.scan_token(ch, start)?,
+            None => Token::EOF(),
+        };
+        Ok(TWL {
+            token,
+            span: self.span_from(start),
+            doc: self.doc.take(),
+        })
+    }
+
+    /// Skips whitespace and comments, keeping doc comments for the next token
+    fn skip_trivia(&mut self) -> Result<(), Error> {
+        while let Some(ch) = self.peek() {
+            let start = self.pos();
+            let kind = if ch == '\n' {
+                self.bump();
+                TriviaKind::Newline
+            } else if ch.is_whitespace() {
+                while self
+                    .peek()
+                    .is_some_and(|ch| ch.is_whitespace() && ch != '\n')
+                {
+                    self.bump();
                 }
-            }
-            if !found {
-                if buffer.len() > 0 && is_valid_identifier(&buffer) {
-                    let mut found = false;
-                    for _type in BUILTIN_TYPES {
-                        if buffer == _type.str {
-                            found = true;
-                            tokens.push(TWL {
-                                token: _type.token,
-                                charn: charn.clone(),
-                                linen: linen.clone(),
-                                filen: filen.clone(),
-                            });
-                            break;
-                        }
-                    }
-                    if !found {
-                        tokens.push(TWL {
-                            token: Token::Identifier(
-                                buffer
-                                    .split(".")
-                                    .collect::<Vec<&str>>()
-                                    .iter()
-                                    .map(|f| f.to_string())
-                                    .collect(),
-                            ),
-                            charn: charn.clone(),
-                            linen: linen.clone(),
-                            filen: filen.clone(),
-                        });
+                TriviaKind::Whitespace
+            } else if self.rest().starts_with("#[") {
+                self.skip_block_comment()?;
+                TriviaKind::BlockComment
+            } else if self.eat("##") {
+                let line = self.take_line();
+                let line = line.strip_prefix(' ').unwrap_or(line).trim_end();
+                match &mut self.doc {
+                    Some(doc) => {
+                        doc.push('\n');
+                        doc.push_str(line);
                     }
-                } else if is_valid_standard_path(&buffer) {
-                    tokens.push(TWL {
-                        token: Token::LibraryPath(buffer.to_owned()),
-                        charn: charn.clone(),
-                        linen: linen.clone(),
-                        filen: filen.clone(),
-                    });
-                } else if is_valid_number(
-                    &buffer,
-                    &Loc {
-                        charn: charn.clone(),
-                        filen: filen.clone(),
-                        linen: linen.clone(),
-                    },
-                )
-                .0
-                {
-                    let valid_num = is_valid_number(
-                        &buffer,
-                        &Loc {
-                            charn: charn.clone(),
-                            filen: filen.clone(),
-                            linen: linen.clone(),
-                        },
-                    );
-                    tokens.push(TWL {
-                        token: Token::NumericLiteral(valid_num.2, valid_num.1),
-                        charn: charn.clone(),
-                        linen: linen.clone(),
-                        filen: filen.clone(),
-                    })
-                } else {
-                    tokens.push(TWL {
-                        token: Token::Unknown(buffer.to_owned()),
-                        charn: charn.clone(),
-                        linen: linen.clone(),
-                        filen: filen.clone(),
-                    });
-                    error_at(
-                        &filen,
-                        &linen,
-                        &charn,
-                        &format!("\"{}\" is not a valid token", &buffer),
-                    );
+                    None => self.doc = Some(line.to_string()),
                 }
+                TriviaKind::DocComment
+            } else if ch == '#' {
+                self.take_line();
+                TriviaKind::LineComment
+            } else {
+                break;
+            };
+            let span = self.span_from(start);
+            if let Some(trivia) = &mut self.trivia {
+                trivia.push(Trivia { kind, span });
             }
-        };
+        }
+        Ok(())
+    }
 
-        for mut i in 0..self.text.len() {
-            let ch = self.text.chars().nth(i).unwrap();
-            if ch == '\n' {
-                linen += 1;
-                charn = 1;
-                in_comment = false;
+    /// Consumes the rest of the line, leaving the line break
+    fn take_line(&mut self) -> &'s str {
+        let start = self.pos();
+        while self.peek().is_some_and(|ch| ch != '\n') {
+            self.bump();
+        }
+        let text = self.text;
+        &text[start..self.pos()]
+    }
+
+    fn skip_block_comment(&mut self) -> Result<(), Error> {
+        let start = self.pos();
+        let mut depth = 0;
+        loop {
+            if self.eat("#[") {
+                depth += 1;
+            } else if self.eat("]#") {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(());
+                }
+            } else if self.bump().is_none() {
+                return Err(self.error(start, "Block comment not ended"));
             }
-            if in_comment && !in_string {
-                charn += 1;
-                continue;
+        }
+    }
+
+    fn scan_token(&mut self, ch: char, start: usize) -> Result<Token, Error> {
+        if self.eat("\"") {
+            return self.scan_string(false, start);
+        }
+        if self.eat("r\"") {
+            return self.scan_string(true, start);
+        }
+        if self.eat("'") {
+            return self.scan_char(start);
+        }
+        if ch.is_ascii_digit() {
+            return self.scan_number(start);
+        }
+        if ch.is_alphabetic() || ch == '_' {
+            return self.scan_word(start);
+        }
+        for sep in SEPERATORS {
+            if self.eat(sep.str) {
+                return Ok(sep.token);
             }
-            if ch == '#' && !in_string {
-                in_comment = true;
-                charn += 1;
-                continue;
+        }
+        Err(self.error(start, &format!("\"{}\" is not a valid token", ch)))
+    }
+
+    /// Scans a keyword, builtin type or dotted identifier such as `std.math.pi`
+    fn scan_word(&mut self, start: usize) -> Result<Token, Error> {
+        while self
+            .peek()
+            .is_some_and(|ch| ch.is_alphanumeric() || ch == '_' || ch == '.')
+        {
+            self.bump();
+        }
+        let text = self.text;
+        let word = &text[start..self.pos()];
+        if let Some(keyword) = KEYWORDS.into_iter().find(|k| k.str == word) {
+            return Ok(keyword.token);
+        }
+        if BUILTIN_TYPES.contains(&word) {
+            return Ok(Token::BuiltinType(word.to_string()));
+        }
+        let parts: Vec<String> = word.split('.').map(|part| part.to_string()).collect();
+        if parts.iter().any(|part| part.is_empty()) {
+            return Err(self.error(start, &format!("\"{}\" is not a valid identifier", word)));
+        }
+        Ok(Token::Identifier(parts))
+    }
+
+    fn scan_number(&mut self, start: usize) -> Result<Token, Error> {
+        let prefixed = ["0x", "0b", "0o"].iter().any(|prefix| {
+            self.rest()
+                .get(..2)
+                .is_some_and(|p| p.eq_ignore_ascii_case(prefix))
+        });
+        let mut previous = ' ';
+        while let Some(ch) = self.peek() {
+            // the sign of an exponent, as in `1e-5`
+            let sign =
+                (ch == '-' || ch == '+') && !prefixed && (previous == 'e' || previous == 'E');
+            if !(ch.is_alphanumeric() || ch == '_' || ch == '.' || sign) {
+                break;
             }
-            if in_string && in_escape {
-                buffer += &(match &ch {
-                    '\\' => '\\'.to_string(),
-                    'a' => (0x07 as char).to_string(),
-                    'b' => (0x08 as char).to_string(),
-                    'e' => (0x1b as char).to_string(),
-                    'f' => (0x0c as char).to_string(),
-                    'n' => '\n'.to_string(),
-                    'r' => '\r'.to_string(),
-                    't' => '\t'.to_string(),
-                    'v' => (0x0b as char).to_string(),
-                    '\'' => '\''.to_string(),
-                    '"' => '"'.to_string(),
-                    _ => error_at(
-                        &filen,
-                        &linen,
-                        &charn,
-                        &format!("Invalid escape character, \\{ch}"),
-                    ),
-                });
-                in_escape = false;
-            } else if in_string && ch == '\\' {
-                in_escape = true;
-            } else if ch == '"' {
-                in_string = !in_string;
-                if !in_string {
-                    tokens.push(TWL {
-                        token: Token::StringLiteral(buffer.to_owned()),
-                        charn: charn.clone(),
-                        linen: linen.clone(),
-                        filen: filen.clone(),
-                    });
-                    buffer = String::from("");
+            previous = ch;
+            self.bump();
+        }
+        let text = self.text;
+        let number = &text[start..self.pos()];
+        parse_number(number).map_err(|reason| {
+            self.error(
+                start,
+                &format!("Malformed numeric literal \"{}\": {}", number, reason),
+            )
+        })
+    }
+
+    /// Scans a string after its opening quote, splitting it into parts
+    /// if it has interpolated expressions
+    fn scan_string(&mut self, raw: bool, start: usize) -> Result<Token, Error> {
+        // the closing quotes of a triple-quoted string and the
+        // indentation to leave out of it
+        let mut triple = None;
+        if self.eat("\"\"") {
+            let body = self.pos();
+            match triple_quoted_layout(self.rest(), raw) {
+                Some((end, dropped)) => {
+                    let dropped: Vec<Range<usize>> = dropped
+                        .into_iter()
+                        .map(|r| r.start + body..r.end + body)
+                        .collect();
+                    triple = Some((end + body, dropped));
                 }
-            } else if in_string {
-                buffer += &ch.to_string();
-            } else if ch == ' ' || ch == '\n' {
-                add_token(&mut buffer, &mut tokens, linen, charn);
-                buffer = String::from("");
-            } else {
-                let mut found = false;
-                for sep in SEPERATORS {
-                    if sep.str.len() == 2 {
-                        let after = &self.text.chars().nth(i + 1);
-                        match after {
-                            Some(v) => {
-                                let ch = ch.to_string() + &v.to_string();
-                                if ch == sep.str {
-                                    i += 1;
-                                    charn += 1;
-                                    add_token(&mut buffer, &mut tokens, linen, charn);
-                                    buffer = String::from("");
-                                    found = true;
-                                    tokens.push(TWL {
-                                        token: sep.token,
-                                        charn,
-                                        linen,
-                                        filen: filen.clone(),
-                                    });
-                                    break;
-                                }
-                            }
-                            _ => {}
-                        }
-                    } else if ch.to_string() == sep.str {
-                        add_token(&mut buffer, &mut tokens, linen, charn);
-                        buffer = String::from("");
-                        found = true;
-                        tokens.push(TWL {
-                            token: sep.token,
-                            charn,
-                            linen,
-                            filen: filen.clone(),
-                        });
-                        break;
+                None => return Err(self.error(start, "Triple-quoted string not ended")),
+            }
+        }
+
+        let mut parts = vec![];
+        let mut buffer = String::new();
+        let mut next_dropped = 0;
+        loop {
+            let pos = self.pos();
+            if let Some((end, dropped)) = &triple {
+                if pos == *end {
+                    self.eat("\"\"\"");
+                    break;
+                }
+                while dropped.get(next_dropped).is_some_and(|r| r.end <= pos) {
+                    next_dropped += 1;
+                }
+                if dropped.get(next_dropped).is_some_and(|r| r.contains(&pos)) {
+                    self.bump();
+                    continue;
+                }
+            }
+            let Some(ch) = self.peek() else {
+                return Err(self.error(start, "String not ended"));
+            };
+            match ch {
+                '"' if triple.is_none() => {
+                    self.bump();
+                    break;
+                }
+                '\\' if !raw => {
+                    let escape = self.pos();
+                    self.bump();
+                    match self.read_escape() {
+                        Ok(escaped) => buffer.push(escaped),
+                        Err(reason) => return Err(self.error(escape, &reason)),
                     }
                 }
-                if !found {
-                    buffer += &ch.to_string();
+                // `{{` and `}}` are literal braces
+                '{' | '}' if !raw && self.rest()[1..].starts_with(ch) => {
+                    self.bump();
+                    self.bump();
+                    buffer.push(ch);
+                }
+                '{' if !raw => {
+                    if !buffer.is_empty() {
+                        parts.push(StringPart::Text(std::mem::take(&mut buffer)));
+                    }
+                    self.bump();
+                    let part = self.scan_interpolation()?;
+                    parts.push(part);
+                }
+                '}' if !raw => {
+                    let brace = self.pos();
+                    self.bump();
+                    return Err(self.error(
+                        brace,
+                        "Unmatched `}` in string, use `}}` for a literal brace",
+                    ));
+                }
+                _ => {
+                    self.bump();
+                    buffer.push(ch);
                 }
             }
+        }
 
-            charn += 1;
+        if parts.is_empty() {
+            return Ok(Token::StringLiteral(buffer));
         }
-        if in_string {
-            error_at(&filen, &linen, &charn, &format!("String not ended"))
+        if !buffer.is_empty() {
+            parts.push(StringPart::Text(buffer));
         }
+        Ok(Token::InterpolatedString(parts))
+    }
 
-        add_token(&mut buffer, &mut tokens, linen, charn);
+    /// Scans an expression embedded in a string, after its opening brace
+    fn scan_interpolation(&mut self) -> Result<StringPart, Error> {
+        let start = self.pos();
+        let mut depth = 0;
+        let mut in_string = false;
+        let mut escaped = false;
+        loop {
+            let Some(ch) = self.peek() else {
+                return Err(self.error(
+                    start - 1,
+                    "Unclosed `{` in string, use `{{` for a literal brace",
+                ));
+            };
+            if in_string {
+                match ch {
+                    _ if escaped => escaped = false,
+                    '\\' => escaped = true,
+                    '"' => in_string = false,
+                    _ => {}
+                }
+            } else {
+                match ch {
+                    '"' => in_string = true,
+                    '{' => depth += 1,
+                    '}' if depth == 0 => break,
+                    '}' => depth -= 1,
+                    _ => {}
+                }
+            }
+            self.bump();
+        }
+        let text = self.text;
+        let source = &text[start..self.pos()];
+        self.bump();
 
-        tokens.push(TWL {
-            charn,
-            linen,
-            filen,
-            token: Token::EOF(),
-        });
-        return tokens;
+        let (expression, spec) = split_format_spec(source);
+        if expression.trim().is_empty() {
+            return Err(self.error(start, "Expected an expression inside `{}`"));
+        }
+        let mut tokens: Vec<TWL> = Scanner::at(expression, self.file.clone(), self.offset + start)
+            .collect::<Result<_, _>>()?;
+        // the expression is parsed on its own, so it doesn't keep the EOF
+        tokens.pop();
+        Ok(StringPart::Value(tokens, spec.map(|s| s.to_string())))
     }
-}
 
-fn is_valid_identifier(str: &String) -> bool {
-    let re = Regex::new(r"^([a-zA-Z0-9_]+\.?)*$").expect("Invalid regex at `is_valid_identifier`");
-    let (first, rem) = str_frem(&str);
-    if str.len() < 1 {
-        return false;
+    /// Reads the escape sequence after a backslash
+    fn read_escape(&mut self) -> Result<char, String> {
+        let ch = self.bump().ok_or("Unfinished escape sequence")?;
+        Ok(match ch {
+            '\\' => '\\',
+            'a' => 0x07 as char,
+            'b' => 0x08 as char,
+            'e' => 0x1b as char,
+            'f' => 0x0c as char,
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            'v' => 0x0b as char,
+            '\'' => '\'',
+            '"' => '"',
+            'x' => {
+                let digits = self.take_hex_digits(2);
+                match u8::from_str_radix(&digits, 16) {
+                    Ok(byte) if digits.len() == 2 => byte as char,
+                    _ => {
+                        return Err(format!(
+                            "Expected two hex digits after \\x, got \\x{digits}"
+                        ))
+                    }
+                }
+            }
+            'u' => {
+                if !self.eat("{") {
+                    return Err("Expected `{` after \\u, as in \\u{1F600}".to_string());
+                }
+                let digits = self.take_hex_digits(7);
+                if !self.eat("}") || digits.is_empty() || digits.len() > 6 {
+                    return Err("Expected 1 to 6 hex digits in \\u{...}".to_string());
+                }
+                match u32::from_str_radix(&digits, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                {
+                    Some(ch) => ch,
+                    None => {
+                        return Err(format!("\\u{{{digits}}} is not a valid unicode character"))
+                    }
+                }
+            }
+            _ => return Err(format!("Invalid escape character, \\{ch}")),
+        })
     }
-    let first_char = first
-        .chars()
-        .nth(0)
-        .expect("Unreachable: `is_valid_identifier`");
-    if first_char.is_alphabetic() || first_char == '_' {
-        if re.is_match(&rem) {
-            return true;
+
+    fn take_hex_digits(&mut self, max: usize) -> String {
+        let mut digits = String::new();
+        while digits.len() < max && self.peek().is_some_and(|ch| ch.is_ascii_hexdigit()) {
+            digits.extend(self.bump());
         }
+        digits
     }
-    return false;
-}
-fn is_valid_standard_path(str: &String) -> bool {
-    let re = Regex::new(r"^<[a-zA-Z0-9_]*>$").expect("Invalid regex at `is_valid_standard_path`");
-    re.is_match(str)
-}
-fn is_valid_number(str: &String, loc: &Loc) -> (bool, String, f64) {
-    let re = Regex::new(r"^[0-9]+(\.[0-9]+f)?$").expect("Invalid regex at `is_valid_number`");
-    if re.is_match(&str) {
-        if str.ends_with("f") {
-            let float = str[0..str.len() - 1].parse::<f64>();
-            match float {
-                Ok(value) => {
-                    return (true, "float".to_owned(), value);
+
+    /// Scans a character literal after its opening quote
+    fn scan_char(&mut self, start: usize) -> Result<Token, Error> {
+        let value = match self.peek() {
+            None | Some('\n') => return Err(self.error(start, "Character literal not ended")),
+            Some('\'') => return Err(self.error(start, "Empty character literal")),
+            Some('\\') => {
+                self.bump();
+                match self.read_escape() {
+                    Ok(escaped) => escaped,
+                    Err(reason) => return Err(self.error(start, &reason)),
                 }
-                Err(_) => error_at(
-                    &loc.filen,
-                    &loc.linen,
-                    &loc.charn,
-                    &format!("Failed to parse float value: {}", &str),
-                ),
             }
-        } else if is_string_numeric(str) {
-            let float = str.parse::<f64>();
-            match float {
-                Ok(value) => {
-                    return (true, "int".to_owned(), value);
-                }
-                Err(_) => return (false, "int".to_owned(), -1.0),
+            Some(ch) => {
+                self.bump();
+                ch
             }
-        } else {
-            return (false, "".to_owned(), -1.0);
+        };
+        if !self.eat("'") {
+            return Err(self.error(
+                start,
+                "Character literals hold exactly one character, use double quotes for strings",
+            ));
         }
-    } else {
-        (false, "".to_owned(), -1.0)
+        Ok(Token::CharLiteral(value))
     }
 }
 
-fn is_string_numeric(str: &String) -> bool {
-    for c in str.chars() {
-        if !c.is_numeric() {
-            return false;
+/// Finds the closing quotes of a triple-quoted string whose body starts
+/// `text`, along with the byte ranges to leave out of it: the line break
+/// after the opening quotes, the line holding the closing quotes, and the
+/// indentation shared by the other lines
+fn triple_quoted_layout(text: &str, raw: bool) -> Option<(usize, Vec<Range<usize>>)> {
+    let mut chars = text.char_indices();
+    let mut end = None;
+    while let Some((i, ch)) = chars.next() {
+        if !raw && ch == '\\' {
+            chars.next();
+        } else if text[i..].starts_with("\"\"\"") {
+            end = Some(i);
+            break;
         }
     }
-    return true;
+    let body = &text[..end?];
+
+    let mut lines = vec![];
+    let mut line_start = 0;
+    for (i, ch) in body.char_indices() {
+        if ch == '\n' {
+            lines.push(line_start..i);
+            line_start = i + 1;
+        }
+    }
+    lines.push(line_start..body.len());
+    let blank = |r: &Range<usize>| body[r.clone()].trim().is_empty();
+    let indent_of = |r: &Range<usize>| {
+        let line = &body[r.clone()];
+        line.len() - line.trim_start_matches([' ', '\t']).len()
+    };
+
+    let mut dropped = vec![];
+    if blank(&lines[0]) {
+        dropped.push(0..lines[0].end + 1);
+    }
+    let mut last = lines.len();
+    if last > 1 && blank(&lines[last - 1]) {
+        dropped.push(lines[last - 1].start - 1..body.len());
+        last -= 1;
+    }
+    let inner = &lines[1..last];
+    let indent = inner
+        .iter()
+        .filter(|r| !blank(r))
+        .map(indent_of)
+        .min()
+        .unwrap_or(0);
+    for r in inner {
+        dropped.push(r.start..r.start + indent.min(indent_of(r)));
+    }
+    dropped.sort_by_key(|r| r.start);
+    Some((body.len(), dropped))
 }
 
-/// Splits a string into the first character
-/// and the remainder
-///
-/// Example (psuedocode):
+/// Splits `value:>8.2` into the expression and its format specifier,
+/// ignoring colons nested in brackets or strings
+fn split_format_spec(source: &str) -> (&str, Option<&str>) {
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, ch) in source.char_indices() {
+        if in_string {
+            match ch {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match ch {
+            '"' => in_string = true,
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            ':' if depth == 0 => return (&source[..i], Some(&source[i + 1..])),
+            _ => {}
+        }
+    }
+    (source, None)
+}
+
+/// Parses a numeric literal, or returns the reason it is malformed
 ///
-/// `str_frem("test") -> ("t", "est")`
-fn str_frem(s: &str) -> (&str, &str) {
-    for i in 1..=s.len() {
-        let r = s.get(0..i);
-        match r {
-            Some(x) => return (x, &s[i..]),
-            None => (),
+/// Supports decimal ints and floats with optional exponents, `0x`, `0b`
+/// and `0o` prefixed ints, `_` digit separators, the `n` suffix for
+/// bigints and the `f` suffix for floats
+fn parse_number(str: &str) -> Result<Token, String> {
+    for (prefix, radix, name) in [
+        ("0x", 16, "hexadecimal"),
+        ("0b", 2, "binary"),
+        ("0o", 8, "octal"),
+    ] {
+        if str.len() >= 2 && str[..2].eq_ignore_ascii_case(prefix) {
+            let digits = &str[2..];
+            let (digits, bigint) = match digits.strip_suffix('n') {
+                Some(digits) => (digits, true),
+                None => (digits, false),
+            };
+            let digits = strip_separators(digits, &format!("after `{prefix}`"))?;
+            if let Some(c) = digits.chars().find(|c| !c.is_digit(radix)) {
+                return Err(format!("\"{c}\" is not a valid {name} digit"));
+            }
+            return Ok(integer_token(&digits, radix, bigint));
+        }
+    }
+
+    let (body, suffix) = match str.chars().last() {
+        Some(c @ ('n' | 'f')) => (&str[..str.len() - 1], Some(c)),
+        _ => (str, None),
+    };
+    let (mantissa, exponent) = match body.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, Some(exponent)),
+        None => (body, None),
+    };
+    let (whole, fraction) = match mantissa.split_once('.') {
+        Some((whole, fraction)) => (whole, Some(fraction)),
+        None => (mantissa, None),
+    };
+
+    let whole = strip_separators(whole, "")?;
+    if let Some(c) = whole.chars().find(|c| !c.is_ascii_digit()) {
+        return Err(format!("\"{c}\" is not a valid digit"));
+    }
+    let fraction = match fraction {
+        Some(fraction) => {
+            let fraction = strip_separators(fraction, "after the decimal point")?;
+            if let Some(c) = fraction.chars().find(|c| !c.is_ascii_digit()) {
+                return Err(format!("\"{c}\" is not a valid digit"));
+            }
+            Some(fraction)
+        }
+        None => None,
+    };
+    let exponent = match exponent {
+        Some(exponent) => {
+            let (sign, digits) = match exponent.strip_prefix(['-', '+']) {
+                Some(digits) => (&exponent[..1], digits),
+                None => ("", exponent),
+            };
+            let digits = strip_separators(digits, "in the exponent")?;
+            if let Some(c) = digits.chars().find(|c| !c.is_ascii_digit()) {
+                return Err(format!("\"{c}\" is not a valid digit in the exponent"));
+            }
+            Some(format!("{sign}{digits}"))
         }
+        None => None,
+    };
+
+    if fraction.is_none() && exponent.is_none() && suffix != Some('f') {
+        return Ok(integer_token(&whole, 10, suffix == Some('n')));
+    }
+    if suffix == Some('n') {
+        return Err("a bigint can't have a fractional part or an exponent".to_string());
+    }
+    let text = format!(
+        "{}.{}e{}",
+        whole,
+        fraction.as_deref().unwrap_or("0"),
+        exponent.as_deref().unwrap_or("0")
+    );
+    match text.parse::<f64>() {
+        Ok(value) if value.is_finite() => Ok(Token::FloatLiteral(value)),
+        _ => Err("the value is too large for a float".to_string()),
+    }
+}
+
+/// Removes `_` digit separators, which are only allowed between digits
+fn strip_separators(digits: &str, location: &str) -> Result<String, String> {
+    if digits.is_empty() {
+        return Err(format!("expected digits {location}").trim_end().to_string());
+    }
+    if digits.starts_with('_') || digits.ends_with('_') || digits.contains("__") {
+        return Err("digit separators must be placed between digits".to_string());
+    }
+    Ok(digits.replace('_', ""))
+}
+
+/// Builds an int token, promoting it to a bigint if it doesn't fit
+fn integer_token(digits: &str, radix: u32, bigint: bool) -> Token {
+    match i64::from_str_radix(digits, radix) {
+        Ok(value) if !bigint => Token::IntegerLiteral(value),
+        _ => Token::BigIntLiteral(
+            BigInt::parse_radix(digits, radix)
+                .expect("Unreachable: digits were validated")
+                .to_string(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The tokens of `source` without the EOF, as their debug text
+    fn tokens(source: &str) -> Vec<String> {
+        let mut tokens = Lexer::new("<test>", source).lex().unwrap();
+        tokens.pop();
+        tokens.iter().map(|t| format!("{:?}", t.token)).collect()
+    }
+
+    fn error(source: &str) -> String {
+        Lexer::new("<test>", source).lex().unwrap_err().reason
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(
+            tokens("42 1_000 0xFF 0B1010 0o17 2.5 1e3 2.5E-1 1_0.0_1 3f"),
+            [
+                "IntegerLiteral(42)",
+                "IntegerLiteral(1000)",
+                "IntegerLiteral(255)",
+                "IntegerLiteral(10)",
+                "IntegerLiteral(15)",
+                "FloatLiteral(2.5)",
+                "FloatLiteral(1000.0)",
+                "FloatLiteral(0.25)",
+                "FloatLiteral(10.01)",
+                "FloatLiteral(3.0)",
+            ]
+        );
+        assert_eq!(
+            tokens("7n 0xffn 9223372036854775808"),
+            [
+                "BigIntLiteral(\"7\")",
+                "BigIntLiteral(\"255\")",
+                "BigIntLiteral(\"9223372036854775808\")",
+            ]
+        );
     }
 
-    (&s[0..0], s)
+    #[test]
+    fn malformed_numbers() {
+        for (source, reason) in [
+            ("0xZZ", "\"Z\" is not a valid hexadecimal digit"),
+            ("0b102", "\"2\" is not a valid binary digit"),
+            ("0o", "expected digits after `0o`"),
+            ("12a", "\"a\" is not a valid digit"),
+            ("1__0", "digit separators must be placed between digits"),
+            ("1_", "digit separators must be placed between digits"),
+            ("1e", "expected digits in the exponent"),
+            (
+                "1.5n",
+                "a bigint can't have a fractional part or an exponent",
+            ),
+            ("1e999", "the value is too large for a float"),
+        ] {
+            assert_eq!(
+                error(source),
+                format!("Malformed numeric literal \"{}\": {}", source, reason)
+            );
+        }
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(
+            tokens(r#""tab\tline\nquote\"slash\\ \x41 \u{1F600} \e[0m""#),
+            [r#"StringLiteral("tab\tline\nquote\"slash\\ A 😀 \u{1b}[0m")"#]
+        );
+        assert_eq!(
+            tokens(r"'\n' '\'' 'é'"),
+            [
+                r"CharLiteral('\n')",
+                r"CharLiteral('\'')",
+                "CharLiteral('é')",
+            ]
+        );
+    }
+
+    #[test]
+    fn bad_escapes() {
+        assert_eq!(error(r#""\q""#), r"Invalid escape character, \q");
+        assert_eq!(
+            error(r#""\x4""#),
+            r"Expected two hex digits after \x, got \x4"
+        );
+        assert_eq!(
+            error(r#""\u41""#),
+            r"Expected `{` after \u, as in \u{1F600}"
+        );
+        assert_eq!(
+            error(r#""\u{1234567}""#),
+            r"Expected 1 to 6 hex digits in \u{...}"
+        );
+        assert_eq!(
+            error(r#""\u{D800}""#),
+            r"\u{D800} is not a valid unicode character"
+        );
+    }
+
+    #[test]
+    fn raw_strings() {
+        assert_eq!(
+            tokens(r#"r"C:\new\{dir}""#),
+            [r#"StringLiteral("C:\\new\\{dir}")"#]
+        );
+    }
+
+    #[test]
+    fn triple_quoted_strings() {
+        let source = "let s = \"\"\"\n        first \"quoted\"\n          second\n        \"\"\"";
+        assert_eq!(
+            tokens(source)[3],
+            r#"StringLiteral("first \"quoted\"\n  second")"#
+        );
+        assert_eq!(
+            tokens("r\"\"\"\n    a\\n{b}\n    \"\"\""),
+            [r#"StringLiteral("a\\n{b}")"#]
+        );
+        assert_eq!(
+            tokens(r#""""on one line""""#),
+            [r#"StringLiteral("on one line")"#]
+        );
+    }
+
+    #[test]
+    fn interpolation() {
+        let source = r#""{{{n:>5}}} of {total} {"x:y"}""#;
+        let tokens = Lexer::new("<test>", source).lex().unwrap();
+        let Token::InterpolatedString(parts) = &tokens[0].token else {
+            panic!("Expected an interpolated string, got {:?}", tokens[0]);
+        };
+        let parts: Vec<String> = parts
+            .iter()
+            .map(|part| match part {
+                StringPart::Text(text) => format!("text {:?}", text),
+                StringPart::Value(tokens, spec) => {
+                    format!(
+                        "value {:?} {:?} at {}",
+                        tokens[0].token, spec, tokens[0].span
+                    )
+                }
+            })
+            .collect();
+        assert_eq!(
+            parts,
+            [
+                r#"text "{""#,
+                r#"value Identifier(["n"]) Some(">5") at <test>:1:5"#,
+                r#"text "} of ""#,
+                r#"value Identifier(["total"]) None at <test>:1:17"#,
+                r#"text " ""#,
+                r#"value StringLiteral("x:y") None at <test>:1:25"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn unfinished_strings() {
+        assert_eq!(error("\"not ended"), "String not ended");
+        assert_eq!(
+            error("\"\"\"\nnot ended\n\""),
+            "Triple-quoted string not ended"
+        );
+        assert_eq!(
+            error("\"{\""),
+            "Unclosed `{` in string, use `{{` for a literal brace"
+        );
+        assert_eq!(
+            error("\"{n"),
+            "Unclosed `{` in string, use `{{` for a literal brace"
+        );
+        assert_eq!(
+            error("\"a}\""),
+            "Unmatched `}` in string, use `}}` for a literal brace"
+        );
+        assert_eq!(error("\"{ }\""), "Expected an expression inside `{}`");
+        assert_eq!(
+            error("'a"),
+            "Character literals hold exactly one character, use double quotes for strings"
+        );
+        assert_eq!(
+            error("'ab'"),
+            "Character literals hold exactly one character, use double quotes for strings"
+        );
+        assert_eq!(error("''"), "Empty character literal");
+        assert_eq!(error("'"), "Character literal not ended");
+    }
+
+    #[test]
+    fn comments() {
+        let source = "#[ outer #[ nested ]# still outer ]# a # line\n## first\n##second\nb";
+        let tokens = Lexer::new("<test>", source).lex().unwrap();
+        let tokens: Vec<String> = tokens
+            .iter()
+            .map(|t| format!("{:?} {:?}", t.token, t.doc))
+            .collect();
+        assert_eq!(
+            tokens,
+            [
+                r#"Identifier(["a"]) None"#,
+                r#"Identifier(["b"]) Some("first\nsecond")"#,
+                "EOF None",
+            ]
+        );
+        assert_eq!(error("#[ #[ nested ]# a"), "Block comment not ended");
+    }
+
+    #[test]
+    fn trivia() {
+        let lexer = Lexer::new("<test>", "a #[ b ]#\t# c\n## d\nz");
+        let mut scanner = lexer.tokens().with_trivia();
+        scanner.next();
+        scanner.next();
+        let kinds: Vec<TriviaKind> = scanner.take_trivia().iter().map(|t| t.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                TriviaKind::Whitespace,
+                TriviaKind::BlockComment,
+                TriviaKind::Whitespace,
+                TriviaKind::LineComment,
+                TriviaKind::Newline,
+                TriviaKind::DocComment,
+                TriviaKind::Newline,
+            ]
+        );
+    }
+
+    #[test]
+    fn unicode() {
+        let tokens = Lexer::new("<test>", "let ünï = \"✓\"\nünï + 'é'")
+            .lex()
+            .unwrap();
+        assert_eq!(tokens[1].span.text().as_deref(), Some("ünï"));
+        assert_eq!(tokens[3].span.text().as_deref(), Some("\"✓\""));
+        assert_eq!(tokens[6].span.to_string(), "<test>:2:7");
+        let error = Lexer::new("<test>", "\"ünï\" $").lex().unwrap_err();
+        assert_eq!(error.reason, "\"$\" is not a valid token");
+        assert_eq!(error.span.to_string(), "<test>:1:7");
+    }
+
+    #[test]
+    fn builtin_types() {
+        let tokens = Lexer::new("<test>", "int str bool float").lex().unwrap();
+        let names: Vec<String> = tokens
+            .into_iter()
+            .filter_map(|t| match t.token {
+                Token::BuiltinType(name) => Some(name),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(names, ["int", "str", "bool"]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn deserialized_tokens_can_be_parsed() {
+        use crate::parser::{Expr, Parser};
+        let lexer = Lexer::new(
+            "serde_tokens.bs",
+            "let x: int = 1\nproc f(s: str): bool then\nend\n",
+        );
+        let json = serde_json::to_string(&lexer.lex().unwrap()).unwrap();
+        // the tokens own everything they hold, so they outlive the json
+        let tokens: Vec<TWL> = serde_json::from_str(&json).unwrap();
+        drop(json);
+        let program = Parser::new(tokens.into_iter().map(Ok))
+            .parse_program()
+            .unwrap();
+        let Expr::Program(lines) = program.expr else {
+            panic!("Expected a program, got {:?}", program.expr);
+        };
+        assert_eq!(lines.len(), 2);
+    }
 }
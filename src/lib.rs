@@ -0,0 +1,32 @@
+mod bigint;
+mod chainmap;
+mod checker;
+#[doc(hidden)]
+pub mod cli;
+pub mod cst;
+mod dump;
+mod engine;
+mod error;
+mod formatter;
+mod interpreter;
+mod io;
+pub mod lexer;
+mod limits;
+mod linter;
+mod native;
+pub mod parser;
+mod repl;
+pub mod source;
+mod testing;
+mod value;
+
+pub use bigint::BigInt;
+pub use engine::Engine;
+pub use error::{Error, ErrorKind};
+pub use io::{BufferIo, Io, ProcessIo};
+pub use limits::{InterruptHandle, Limits};
+pub use native::{FromValue, IntoValue, NativeFn, NativeModule, NativeProc};
+pub use value::{Procedure, Value};
+
+#[cfg(feature = "macros")]
+pub use bull_script_macros::{bull_fn, bull_module};
@@ -0,0 +1,353 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::error::{Error, ErrorKind};
+use crate::source::Span;
+
+/// How many bytes of the Rust stack scripts may use unless the limits say
+/// otherwise, which keeps endless recursion from overflowing the stack of
+/// the thread running the script. Threads are given 2 MiB of stack unless
+/// they ask for more, so this leaves the host half of that.
+///
+/// Each call nested in a script takes around 2.5 KiB in release builds and
+/// 20 KiB in debug builds, more for calls in the middle of big expressions,
+/// so this allows a few hundred of them, or about 50 in a debug build.
+pub const DEFAULT_MAX_STACK: usize = 1024 * 1024;
+
+/// How often, in steps, the clock and the interrupt handle are checked, as
+/// checking them on every step would slow scripts down
+const CHECK_INTERVAL: u64 = 1024;
+
+/// How much a script may do before it is stopped, for running scripts that
+/// can't be trusted. Going over a limit raises an error of its own
+/// `ErrorKind`, which the host gets back but scripts can't catch.
+///
+/// A step is a statement or expression being run. Steps, time and memory
+/// are counted from when the limits are set or restarted, which an `Engine`
+/// does every time it runs something.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    pub(crate) max_steps: Option<u64>,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) max_call_depth: Option<usize>,
+    pub(crate) max_heap: Option<usize>,
+    pub(crate) max_stack: Option<usize>,
+}
+
+impl Limits {
+    /// Only using `DEFAULT_MAX_STACK` bytes of the stack, like an
+    /// interpreter without any limits set
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// No limits at all, not even on the stack, for hosts that make sure
+    /// their threads have the stack for what they run
+    pub fn unlimited() -> Self {
+        Self {
+            max_stack: None,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_max_steps(mut self, steps: u64) -> Self {
+        self.max_steps = Some(steps);
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn with_max_call_depth(mut self, depth: usize) -> Self {
+        self.max_call_depth = Some(depth);
+        self
+    }
+
+    /// Limits the bytes taken up by the strings, lists, maps and bigints
+    /// the script keeps in variables and makes while running. This is an
+    /// estimate, so a script may go a little over it.
+    pub fn with_max_heap(mut self, bytes: usize) -> Self {
+        self.max_heap = Some(bytes);
+        self
+    }
+
+    /// Limits the bytes of the Rust stack used from where the script was
+    /// started, which is how deep its calls can be nested. Running the
+    /// script on a thread with a bigger stack lets this be raised.
+    pub fn with_max_stack(mut self, bytes: usize) -> Self {
+        self.max_stack = Some(bytes);
+        self
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_steps: None,
+            timeout: None,
+            max_call_depth: None,
+            max_heap: None,
+            max_stack: Some(DEFAULT_MAX_STACK),
+        }
+    }
+}
+
+/// Stops a script running on another thread, which raises an
+/// `Interrupted` error within a few steps. A script blocked reading input
+/// is stopped once the read returns. Interrupting when no script is running
+/// does nothing, as the next run starts afresh.
+#[derive(Debug, Clone, Default)]
+pub struct InterruptHandle {
+    interrupted: Arc<AtomicBool>,
+}
+
+impl InterruptHandle {
+    pub fn interrupt(&self) {
+        self.interrupted.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the script should stop, which it only needs to be told once
+    fn take(&self) -> bool {
+        self.interrupted.swap(false, Ordering::Relaxed)
+    }
+
+    fn clear(&self) {
+        self.interrupted.store(false, Ordering::Relaxed);
+    }
+}
+
+/// A limit a script went over
+pub(crate) struct Exceeded {
+    kind: ErrorKind,
+    reason: String,
+}
+
+impl Exceeded {
+    fn new(kind: ErrorKind, reason: String) -> Self {
+        Self { kind, reason }
+    }
+
+    /// The error to raise where the script was when it went over
+    pub fn at(self, span: Span) -> Error {
+        Error {
+            span,
+            reason: self.reason,
+            kind: self.kind,
+        }
+    }
+}
+
+/// What a script has used of its limits so far
+pub(crate) struct Budget {
+    pub limits: Limits,
+    steps: u64,
+    started: Instant,
+    /// The heap in use, which is only measured when this estimate goes over
+    /// the limit and otherwise only counts up
+    heap: usize,
+    /// Where the stack was when the script started
+    stack_start: usize,
+    interrupt: InterruptHandle,
+}
+
+impl Budget {
+    pub fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            steps: 0,
+            started: Instant::now(),
+            heap: 0,
+            stack_start: stack_position(),
+            interrupt: InterruptHandle::default(),
+        }
+    }
+
+    /// Starts counting again, for a new run, forgetting any interrupt that
+    /// came after the last run ended
+    pub fn restart(&mut self) {
+        self.steps = 0;
+        self.started = Instant::now();
+        self.heap = 0;
+        self.stack_start = stack_position();
+        self.interrupt.clear();
+    }
+
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupt.clone()
+    }
+
+    /// Counts a step, checking the stack the script is using, and the time
+    /// and whether the script was interrupted every so often
+    pub fn step(&mut self) -> Result<(), Exceeded> {
+        self.stack()?;
+        self.steps += 1;
+        if let Some(max) = self.limits.max_steps {
+            if self.steps > max {
+                return Err(Exceeded::new(
+                    ErrorKind::StepLimit,
+                    format!("Ran for more than {} steps", max),
+                ));
+            }
+        }
+        if !self.steps.is_multiple_of(CHECK_INTERVAL) {
+            return Ok(());
+        }
+        if self.interrupt.take() {
+            return Err(Exceeded::new(
+                ErrorKind::Interrupted,
+                "Interrupted".to_string(),
+            ));
+        }
+        match self.limits.timeout {
+            Some(timeout) if self.started.elapsed() > timeout => Err(Exceeded::new(
+                ErrorKind::Timeout,
+                format!("Ran for longer than {:?}", timeout),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks a call can be made with `depth` calls already running
+    pub fn call(&self, depth: usize) -> Result<(), Exceeded> {
+        if let Some(max) = self.limits.max_call_depth {
+            if depth >= max {
+                return Err(Exceeded::new(
+                    ErrorKind::CallDepth,
+                    format!("Calls nested more than {} deep", max),
+                ));
+            }
+        }
+        match self.limits.max_stack {
+            // calls stop an eighth short of the limit, leaving the rest for
+            // the expressions in them, so endless recursion is always
+            // stopped at a call
+            Some(max) if self.stack_used() > max - max / 8 => Err(Exceeded::new(
+                ErrorKind::CallDepth,
+                format!("Calls nested too deep for {} bytes of stack", max),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks the script hasn't used more of the stack than it may, which
+    /// expressions nested in each other use up as well as calls
+    fn stack(&self) -> Result<(), Exceeded> {
+        match self.limits.max_stack {
+            Some(max) if self.stack_used() > max => Err(Exceeded::new(
+                ErrorKind::CallDepth,
+                format!("Nested too deep for {} bytes of stack", max),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn stack_used(&self) -> usize {
+        stack_position().abs_diff(self.stack_start)
+    }
+
+    /// Counts `size` more bytes of heap, giving whether the estimate is
+    /// still within the limit. If it isn't, the heap in use should be
+    /// measured with `measure_heap`.
+    pub fn allocate(&mut self, size: usize) -> bool {
+        self.heap = self.heap.saturating_add(size);
+        self.limits.max_heap.is_none_or(|max| self.heap <= max)
+    }
+
+    /// Replaces the estimate with the heap really in use
+    pub fn measure_heap(&mut self, in_use: usize) -> Result<(), Exceeded> {
+        self.heap = in_use;
+        match self.limits.max_heap {
+            Some(max) if in_use > max => Err(Exceeded::new(
+                ErrorKind::HeapLimit,
+                format!("Used more than {} bytes of memory", max),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Roughly where the top of the stack is, which is close enough to tell how
+/// much of it is in use
+#[inline(never)]
+pub(crate) fn stack_position() -> usize {
+    let marker = 0u8;
+    std::hint::black_box(&marker) as *const u8 as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps() {
+        let mut budget = Budget::new(Limits::new().with_max_steps(10));
+        for _ in 0..10 {
+            assert!(budget.step().is_ok());
+        }
+        let exceeded = budget.step().unwrap_err();
+        assert_eq!(exceeded.kind, ErrorKind::StepLimit);
+        budget.restart();
+        assert!(budget.step().is_ok());
+    }
+
+    #[test]
+    fn call_depth() {
+        let budget = Budget::new(Limits::new().with_max_call_depth(3));
+        assert!(budget.call(2).is_ok());
+        assert_eq!(budget.call(3).unwrap_err().kind, ErrorKind::CallDepth);
+    }
+
+    /// Steps with `depth` frames of the stack taken up by the ones before
+    fn step_nested(budget: &mut Budget, depth: usize) -> Result<(), Exceeded> {
+        let frame = std::hint::black_box([0u8; 1024]);
+        budget.step()?;
+        if depth > 0 {
+            step_nested(budget, depth - 1)?;
+        }
+        std::hint::black_box(&frame);
+        Ok(())
+    }
+
+    #[test]
+    fn stack() {
+        let mut budget = Budget::new(Limits::new().with_max_stack(64 * 1024));
+        assert!(step_nested(&mut budget, 10).is_ok());
+        let exceeded = step_nested(&mut budget, 100).unwrap_err();
+        assert_eq!(exceeded.kind, ErrorKind::CallDepth);
+        let mut budget = Budget::new(Limits::unlimited());
+        assert!(step_nested(&mut budget, 100).is_ok());
+    }
+
+    #[test]
+    fn heap() {
+        let mut budget = Budget::new(Limits::new().with_max_heap(100));
+        assert!(budget.allocate(60));
+        assert!(!budget.allocate(60));
+        assert!(budget.measure_heap(50).is_ok());
+        assert!(budget.allocate(40));
+        assert_eq!(
+            budget.measure_heap(101).unwrap_err().kind,
+            ErrorKind::HeapLimit
+        );
+    }
+
+    #[test]
+    fn interrupts_are_only_seen_once() {
+        let mut budget = Budget::new(Limits::new());
+        budget.interrupt_handle().interrupt();
+        let stopped = (0..CHECK_INTERVAL).find_map(|_| budget.step().err());
+        assert_eq!(stopped.unwrap().kind, ErrorKind::Interrupted);
+        assert!((0..CHECK_INTERVAL).all(|_| budget.step().is_ok()));
+    }
+
+    #[test]
+    fn restarting_forgets_interrupts() {
+        let mut budget = Budget::new(Limits::new());
+        budget.interrupt_handle().interrupt();
+        budget.restart();
+        assert!((0..CHECK_INTERVAL * 2).all(|_| budget.step().is_ok()));
+    }
+}
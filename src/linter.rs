@@ -0,0 +1,677 @@
+use std::{collections::HashSet, fmt::Display};
+
+use crate::error::write_snippet;
+use crate::interpreter::LIBRARIES;
+use crate::lexer::Lexer;
+use crate::parser::{Expr, ExprWL, InterpolationPart, Parser, Type};
+use crate::source::Span;
+
+/// A check the linter makes, which can be turned off in the config
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rule {
+    UnusedVariable,
+    UnusedParameter,
+    UnreachableCode,
+    ShadowedBinding,
+    SelfAssignment,
+    ConstantCondition,
+    UnboundedRecursion,
+    UnusedImport,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 8] = [
+        Rule::UnusedVariable,
+        Rule::UnusedParameter,
+        Rule::UnreachableCode,
+        Rule::ShadowedBinding,
+        Rule::SelfAssignment,
+        Rule::ConstantCondition,
+        Rule::UnboundedRecursion,
+        Rule::UnusedImport,
+    ];
+
+    /// The name the rule is written as in the config
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rule::UnusedVariable => "unused-variable",
+            Rule::UnusedParameter => "unused-parameter",
+            Rule::UnreachableCode => "unreachable-code",
+            Rule::ShadowedBinding => "shadowed-binding",
+            Rule::SelfAssignment => "self-assignment",
+            Rule::ConstantCondition => "constant-condition",
+            Rule::UnboundedRecursion => "unbounded-recursion",
+            Rule::UnusedImport => "unused-import",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Rule> {
+        Rule::ALL.into_iter().find(|rule| rule.name() == name)
+    }
+}
+
+/// A warning found by the linter
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lint {
+    pub rule: Rule,
+    pub span: Span,
+    pub reason: String,
+}
+
+/// Shows the warning like an `Error`, coloured in the alternate form
+impl Display for Lint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = if f.alternate() {
+            "\x1b[33;1m[WARNING]\x1b[0m"
+        } else {
+            "[WARNING]"
+        };
+        write!(
+            f,
+            "{}: At {}:\n\t{} ({})\n",
+            label,
+            self.span,
+            self.reason,
+            self.rule.name()
+        )?;
+        write_snippet(f, &self.span)
+    }
+}
+
+/// Which rules are turned on. Every rule is on by default, and a config
+/// file turns them on or off with one `rule = on` or `rule = off` per line.
+///
+/// ```text
+/// # loop counters are often left unused
+/// unused-variable = off
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct LintConfig {
+    disabled: HashSet<Rule>,
+}
+
+impl LintConfig {
+    pub fn parse(text: &str) -> Result<LintConfig, String> {
+        let mut config = LintConfig::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some((name, value)) = line.split_once('=') else {
+                return Err(format!(
+                    "Line {}: expected `rule = on` or `rule = off`",
+                    i + 1
+                ));
+            };
+            let Some(rule) = Rule::from_name(name.trim()) else {
+                return Err(format!("Line {}: unknown rule `{}`", i + 1, name.trim()));
+            };
+            match value.trim() {
+                "on" => config.disabled.remove(&rule),
+                "off" => config.disabled.insert(rule),
+                value => {
+                    return Err(format!(
+                        "Line {}: expected `on` or `off`, got `{}`",
+                        i + 1,
+                        value
+                    ))
+                }
+            };
+        }
+        Ok(config)
+    }
+
+    pub fn enabled(&self, rule: Rule) -> bool {
+        !self.disabled.contains(&rule)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Local {
+    name: String,
+    span: Span,
+    parameter: bool,
+    used: bool,
+}
+
+/// Walks the AST looking for code that is probably a mistake
+pub struct Linter {
+    config: LintConfig,
+    /// The scopes of the proc being linted, innermost last
+    locals: Option<Vec<Vec<Local>>>,
+    namespace: Vec<String>,
+    /// Global names used anywhere, each with the namespace it was used in
+    references: Vec<(Vec<String>, Vec<String>)>,
+    imports: Vec<ExprWL>,
+    lints: Vec<Lint>,
+}
+
+macro_rules! lint {
+    ($self: ident, $rule: expr, $loc: expr, $reason: expr) => {
+        if $self.config.enabled($rule) {
+            $self.lints.push(Lint {
+                rule: $rule,
+                span: $loc.span.clone(),
+                reason: $reason,
+            })
+        }
+    };
+}
+
+/// Whether an expression always has the same value
+fn is_constant(ex: &ExprWL) -> bool {
+    match &ex.expr {
+        Expr::Literal(..) => true,
+        Expr::Group(inner) | Expr::Unary(_, inner) => is_constant(inner),
+        Expr::Binary(_, left, right) => is_constant(left) && is_constant(right),
+        Expr::Interpolation(parts) => parts.iter().all(|part| match part {
+            InterpolationPart::Text(_) => true,
+            InterpolationPart::Value(value, _) => is_constant(value),
+        }),
+        _ => false,
+    }
+}
+
+/// Whether a `return` can be reached from a statement, not counting the
+/// ones in procs declared inside it
+fn contains_return(ex: &ExprWL) -> bool {
+    match &ex.expr {
+        Expr::Return(_) => true,
+        Expr::If(_, body) | Expr::While(_, body) | Expr::For(_, _, _, body) => {
+            body.iter().any(contains_return)
+        }
+        _ => false,
+    }
+}
+
+/// The first call to one of `names` that is always made when `ex` is
+/// evaluated, not looking inside blocks or procs declared in it
+fn find_call<'e>(ex: &'e ExprWL, names: &[Vec<String>]) -> Option<&'e ExprWL> {
+    match &ex.expr {
+        Expr::Call(parts, args) => args
+            .iter()
+            .find_map(|arg| find_call(arg, names))
+            .or_else(|| names.contains(parts).then_some(ex)),
+        Expr::Group(inner) | Expr::Unary(_, inner) | Expr::Return(inner) => find_call(inner, names),
+        Expr::Binary(_, left, right) => find_call(left, names).or_else(|| find_call(right, names)),
+        Expr::VariableDeclaration(_, _, value)
+        | Expr::ConstantDeclaration(_, _, value)
+        | Expr::VariableSet(_, value) => find_call(value, names),
+        Expr::If(cond, _) | Expr::While(cond, _) => find_call(cond, names),
+        Expr::For(_, start, end, _) => find_call(start, names).or_else(|| find_call(end, names)),
+        Expr::Interpolation(parts) => parts.iter().find_map(|part| match part {
+            InterpolationPart::Value(value, _) => find_call(value, names),
+            InterpolationPart::Text(_) => None,
+        }),
+        _ => None,
+    }
+}
+
+/// The full name of every global a library declares
+fn library_globals(name: &str) -> Vec<Vec<String>> {
+    fn collect(code: &[ExprWL], namespace: &mut Vec<String>, globals: &mut Vec<Vec<String>>) {
+        for ex in code {
+            match &ex.expr {
+                Expr::Namespace(name, body) => {
+                    let depth = namespace.len();
+                    namespace.extend(name.iter().cloned());
+                    collect(body, namespace, globals);
+                    namespace.truncate(depth);
+                }
+                Expr::Proc(name, ..)
+                | Expr::VariableDeclaration(name, ..)
+                | Expr::ConstantDeclaration(name, ..) => {
+                    let mut path = namespace.clone();
+                    path.extend(name.iter().cloned());
+                    globals.push(path);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut globals = vec![];
+    if let Some((_, source)) = LIBRARIES.iter().find(|(lib, _)| *lib == name) {
+        let lexer = Lexer::new(&format!("<{}>", name), source);
+        let mut parser = Parser::new(lexer.tokens());
+        if let Ok(ExprWL {
+            expr: Expr::Program(code),
+            ..
+        }) = parser.parse_program()
+        {
+            collect(&code, &mut vec![], &mut globals);
+        }
+    }
+    globals
+}
+
+impl Linter {
+    pub fn new(config: LintConfig) -> Self {
+        Self {
+            config,
+            locals: None,
+            namespace: vec![],
+            references: vec![],
+            imports: vec![],
+            lints: vec![],
+        }
+    }
+
+    /// Lints a whole program, returning the warnings in source order
+    pub fn lint_program(&mut self, prog: &ExprWL) -> Vec<Lint> {
+        if let Expr::Program(code) = &prog.expr {
+            self.lint_block(code);
+        }
+        self.unused_imports();
+        let mut lints = std::mem::take(&mut self.lints);
+        lints.sort_by_key(|lint| (lint.span.start, lint.span.end));
+        lints
+    }
+
+    fn unused_imports(&mut self) {
+        for import in std::mem::take(&mut self.imports) {
+            let Expr::Import(_, name) = &import.expr else {
+                continue;
+            };
+            let globals = library_globals(name);
+            let used = self.references.iter().any(|(parts, namespace)| {
+                (0..=namespace.len()).any(|i| {
+                    let mut path = namespace[..i].to_vec();
+                    path.extend(parts.iter().cloned());
+                    globals.contains(&path)
+                })
+            });
+            if !used {
+                lint!(
+                    self,
+                    Rule::UnusedImport,
+                    import,
+                    format!("`{}` is imported but never used", name)
+                );
+            }
+        }
+    }
+
+    /// Lints the statements of a block, which has its own scope
+    fn lint_block(&mut self, code: &[ExprWL]) {
+        if let Some(locals) = &mut self.locals {
+            locals.push(vec![]);
+        }
+        let mut returned = false;
+        for ex in code {
+            if returned {
+                lint!(
+                    self,
+                    Rule::UnreachableCode,
+                    ex,
+                    "This code is never run, as it comes after a `return`".to_string()
+                );
+                returned = false;
+            }
+            self.lint(ex);
+            if let Expr::Return(_) = ex.expr {
+                returned = true;
+            }
+        }
+        self.pop_scope();
+    }
+
+    fn pop_scope(&mut self) {
+        let Some(scope) = self.locals.as_mut().and_then(|locals| locals.pop()) else {
+            return;
+        };
+        for local in scope {
+            if local.used || local.name.starts_with('_') {
+                continue;
+            }
+            if local.parameter {
+                lint!(
+                    self,
+                    Rule::UnusedParameter,
+                    local,
+                    format!("Parameter `{}` is never used", local.name)
+                );
+            } else {
+                lint!(
+                    self,
+                    Rule::UnusedVariable,
+                    local,
+                    format!("`{}` is declared but never used", local.name)
+                );
+            }
+        }
+    }
+
+    /// Declares a local, warning if it hides another one
+    fn declare(&mut self, name: &[String], span: Span, parameter: bool, used: bool) {
+        let name = name.join(".");
+        let Some(locals) = &mut self.locals else {
+            return;
+        };
+        let shadowed = locals.iter().flatten().any(|local| local.name == name);
+        let local = Local {
+            name,
+            span,
+            parameter,
+            used,
+        };
+        if let Some(scope) = locals.last_mut() {
+            scope.push(local.clone());
+        }
+        if shadowed {
+            lint!(
+                self,
+                Rule::ShadowedBinding,
+                local,
+                format!("`{}` shadows an earlier declaration", local.name)
+            );
+        }
+    }
+
+    /// Marks a name as used, either a local or a global
+    fn use_name(&mut self, parts: &[String]) {
+        let name = parts.join(".");
+        let local = self
+            .locals
+            .iter_mut()
+            .flatten()
+            .rev()
+            .flat_map(|scope| scope.iter_mut().rev())
+            .find(|local| local.name == name);
+        match local {
+            Some(local) => local.used = true,
+            None => self
+                .references
+                .push((parts.to_vec(), self.namespace.clone())),
+        }
+    }
+
+    fn lint(&mut self, ex: &ExprWL) {
+        match &ex.expr {
+            Expr::VariableDeclaration(name, _, value)
+            | Expr::ConstantDeclaration(name, _, value) => {
+                self.lint(value);
+                self.declare(name, ex.span.clone(), false, false);
+            }
+            Expr::VariableSet(name, value) => {
+                if matches!(&value.expr, Expr::Identifier(parts) if parts == name) {
+                    lint!(
+                        self,
+                        Rule::SelfAssignment,
+                        ex,
+                        format!("`{}` is assigned to itself", name.join("."))
+                    );
+                }
+                self.lint(value);
+                // assigning to a local doesn't count as using it
+                let local = self
+                    .locals
+                    .iter()
+                    .flatten()
+                    .flatten()
+                    .any(|l| l.name == name.join("."));
+                if !local {
+                    self.use_name(name);
+                }
+            }
+            Expr::Identifier(parts) => self.use_name(parts),
+            Expr::Call(parts, args) => {
+                self.use_name(parts);
+                for arg in args {
+                    self.lint(arg);
+                }
+            }
+            Expr::If(cond, body) | Expr::While(cond, body) => {
+                if is_constant(cond) {
+                    let keyword = if let Expr::If(..) = ex.expr {
+                        "if"
+                    } else {
+                        "while"
+                    };
+                    lint!(
+                        self,
+                        Rule::ConstantCondition,
+                        cond,
+                        format!("The condition of this `{}` is always the same", keyword)
+                    );
+                }
+                self.lint(cond);
+                self.lint_block(body);
+            }
+            Expr::For(name, start, end, body) => {
+                self.lint(start);
+                self.lint(end);
+                if let Some(locals) = &mut self.locals {
+                    locals.push(vec![]);
+                }
+                // loops are often run for their side effects alone
+                self.declare(name, ex.span.clone(), false, true);
+                self.lint_block(body);
+                self.pop_scope();
+            }
+            Expr::Proc(name, params, _, body) => self.lint_proc(ex, name, params, body),
+            Expr::Test(_, body) => {
+                // a test has its own locals, like a proc
+                let outer = self.locals.replace(vec![vec![]]);
+                self.lint_block(body);
+                self.pop_scope();
+                self.locals = outer;
+            }
+            Expr::Namespace(name, body) => {
+                let depth = self.namespace.len();
+                self.namespace.extend(name.iter().cloned());
+                self.lint_block(body);
+                self.namespace.truncate(depth);
+            }
+            Expr::Import(false, _) => self.imports.push(ex.clone()),
+            Expr::Group(inner) | Expr::Unary(_, inner) | Expr::Return(inner) => self.lint(inner),
+            Expr::Binary(_, left, right) => {
+                self.lint(left);
+                self.lint(right);
+            }
+            Expr::Interpolation(parts) => {
+                for part in parts {
+                    if let InterpolationPart::Value(value, _) = part {
+                        self.lint(value);
+                    }
+                }
+            }
+            Expr::Program(code) => self.lint_block(code),
+            Expr::Literal(..) | Expr::Import(true, _) => {}
+        }
+    }
+
+    fn lint_proc(
+        &mut self,
+        ex: &ExprWL,
+        name: &[String],
+        params: &[(String, Option<Type>)],
+        body: &[ExprWL],
+    ) {
+        // procs can't see the locals of the proc they are declared in
+        let outer = self.locals.replace(vec![vec![]]);
+        for (param, _) in params {
+            self.declare(std::slice::from_ref(param), ex.span.clone(), true, false);
+        }
+        self.lint_block(body);
+        self.pop_scope();
+        self.locals = outer;
+
+        let mut full_name = self.namespace.clone();
+        full_name.extend(name.iter().cloned());
+        self.unbounded_recursion(&[name.to_vec(), full_name], body);
+    }
+
+    /// Warns about a proc that always calls itself before it can return
+    fn unbounded_recursion(&mut self, names: &[Vec<String>], body: &[ExprWL]) {
+        for ex in body {
+            if let Some(call) = find_call(ex, names) {
+                lint!(
+                    self,
+                    Rule::UnboundedRecursion,
+                    call,
+                    format!(
+                        "`{}` always calls itself, so it never returns",
+                        names[0].join(".")
+                    )
+                );
+                return;
+            }
+            if contains_return(ex) {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lint_with(config: LintConfig, source: &str) -> Vec<String> {
+        let lexer = Lexer::new("<test>", source);
+        let program = Parser::new(lexer.tokens()).parse_program().unwrap();
+        Linter::new(config)
+            .lint_program(&program)
+            .into_iter()
+            .map(|lint| format!("{}: {}", lint.rule.name(), lint.reason))
+            .collect()
+    }
+
+    fn lint(source: &str) -> Vec<String> {
+        lint_with(LintConfig::default(), source)
+    }
+
+    #[test]
+    fn unused_variable() {
+        assert_eq!(
+            lint("proc f() then\n    let x = 1\nend"),
+            ["unused-variable: `x` is declared but never used"]
+        );
+        assert!(lint("proc f() then\n    let x = 1\n    return x\nend").is_empty());
+        // globals and names starting with `_` may go unused
+        assert!(lint("let x = 1\nproc f() then\n    let _x = 1\nend").is_empty());
+        // assigning to a local isn't using it
+        assert_eq!(
+            lint("proc f() then\n    let x = 1\n    x = 2\nend"),
+            ["unused-variable: `x` is declared but never used"]
+        );
+    }
+
+    #[test]
+    fn unused_parameter() {
+        assert_eq!(
+            lint("proc f(a, b) then\n    return a\nend"),
+            ["unused-parameter: Parameter `b` is never used"]
+        );
+        assert!(lint("proc f(a, _b) then\n    return a\nend").is_empty());
+    }
+
+    #[test]
+    fn unreachable_code() {
+        assert_eq!(
+            lint("proc f() then\n    return 1\n    f()\nend"),
+            ["unreachable-code: This code is never run, as it comes after a `return`"]
+        );
+        assert!(lint(
+            "proc f(a) then\n    if a then\n        return 1\n    end\n    return 2\nend"
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn shadowed_binding() {
+        assert_eq!(
+            lint(
+                "proc f(a) then\n    if a then\n        let a = 2\n        return a\n    end\nend"
+            ),
+            ["shadowed-binding: `a` shadows an earlier declaration"]
+        );
+        // procs don't see the locals of the proc around them
+        assert!(lint(
+            "proc f(a) then\n    proc g(a) then\n        return a\n    end\n    return g(a)\nend"
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn self_assignment() {
+        assert_eq!(
+            lint("let x = 1\nx = x"),
+            ["self-assignment: `x` is assigned to itself"]
+        );
+        assert!(lint("let x = 1\nx = x + 1").is_empty());
+    }
+
+    #[test]
+    fn constant_condition() {
+        assert_eq!(
+            lint("if 1 + 1 is 2 then\nend\nwhile true then\nend"),
+            [
+                "constant-condition: The condition of this `if` is always the same",
+                "constant-condition: The condition of this `while` is always the same",
+            ]
+        );
+        assert!(lint("let x = 1\nif x is 2 then\nend").is_empty());
+    }
+
+    #[test]
+    fn unbounded_recursion() {
+        assert_eq!(
+            lint("namespace ns then\n    proc f(n) then\n        return ns.f(n - 1)\n    end\nend"),
+            ["unbounded-recursion: `f` always calls itself, so it never returns"]
+        );
+        assert!(lint("proc f(n) then\n    if n is 0 then\n        return 0\n    end\n    return f(n - 1)\nend").is_empty());
+    }
+
+    #[test]
+    fn unused_import() {
+        assert_eq!(
+            lint("import std\nimport math\nstd.printval(1)"),
+            ["unused-import: `math` is imported but never used"]
+        );
+        // names used inside a namespace are found relative to it
+        assert!(lint("import math\nnamespace std then\n    let x = math.pi\nend\nx").is_empty());
+    }
+
+    #[test]
+    fn rules_can_be_turned_off() {
+        let config =
+            LintConfig::parse("# loop counters\nunused-variable = off\nself-assignment = on\n")
+                .unwrap();
+        assert!(!config.enabled(Rule::UnusedVariable));
+        assert!(config.enabled(Rule::SelfAssignment));
+        assert_eq!(
+            lint_with(config, "proc f() then\n    let x = 1\n    x = x\nend"),
+            ["self-assignment: `x` is assigned to itself"]
+        );
+    }
+
+    #[test]
+    fn warnings_are_only_coloured_when_asked() {
+        let lexer = Lexer::new("<test>", "let x = 1\nx = x");
+        let program = Parser::new(lexer.tokens()).parse_program().unwrap();
+        let lints = Linter::new(LintConfig::default()).lint_program(&program);
+        assert!(lints[0]
+            .to_string()
+            .starts_with("[WARNING]: At <test>:2:1:"));
+        assert!(format!("{:#}", lints[0]).starts_with("\x1b[33;1m[WARNING]\x1b[0m: At"));
+    }
+
+    #[test]
+    fn bad_configs() {
+        assert_eq!(
+            LintConfig::parse("unused-variable").unwrap_err(),
+            "Line 1: expected `rule = on` or `rule = off`"
+        );
+        assert_eq!(
+            LintConfig::parse("\nunused-thing = off").unwrap_err(),
+            "Line 2: unknown rule `unused-thing`"
+        );
+        assert_eq!(
+            LintConfig::parse("unused-variable = maybe").unwrap_err(),
+            "Line 1: expected `on` or `off`, got `maybe`"
+        );
+    }
+}
@@ -0,0 +1,396 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Debug, Display};
+
+use crate::bigint::BigInt;
+use crate::parser::Type;
+use crate::value::Value;
+
+/// A Rust type a script value can be passed to a native proc as
+pub trait FromValue: Sized {
+    /// The type scripts are told to pass when they pass something else
+    fn ty() -> Type;
+    /// Converts the value, or gives `None` if it is the wrong type
+    fn from_value(value: Value) -> Option<Self>;
+}
+
+/// A Rust type a native proc can return to a script
+pub trait IntoValue {
+    /// Converts into a value, or the reason the native proc failed
+    fn into_value(self) -> Result<Value, String>;
+}
+
+impl FromValue for Value {
+    fn ty() -> Type {
+        Type::Any
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        Some(value)
+    }
+}
+
+impl IntoValue for Value {
+    fn into_value(self) -> Result<Value, String> {
+        Ok(self)
+    }
+}
+
+impl IntoValue for () {
+    fn into_value(self) -> Result<Value, String> {
+        Ok(Value::Null)
+    }
+}
+
+impl FromValue for bool {
+    fn ty() -> Type {
+        Type::Bool
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::Boolean(b) => Some(b),
+            _ => None,
+        }
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self) -> Result<Value, String> {
+        Ok(Value::Boolean(self))
+    }
+}
+
+/// Integers that don't fit the Rust type are the wrong type, and ones that
+/// don't fit an `int` are returned as a `bigint`
+macro_rules! integer {
+    ($($ty: ty),*) => {
+        $(
+            impl FromValue for $ty {
+                fn ty() -> Type {
+                    Type::Int
+                }
+
+                fn from_value(value: Value) -> Option<Self> {
+                    match value {
+                        Value::Integer(i) => <$ty>::try_from(i).ok(),
+                        // such as a `u64` too big for an `int`
+                        Value::BigInt(b) => b.to_string().parse().ok(),
+                        _ => None,
+                    }
+                }
+            }
+
+            impl IntoValue for $ty {
+                fn into_value(self) -> Result<Value, String> {
+                    Ok(match i64::try_from(self) {
+                        Ok(i) => Value::Integer(i),
+                        Err(_) => Value::BigInt(BigInt::parse(&self.to_string()).unwrap()),
+                    })
+                }
+            }
+        )*
+    };
+}
+
+integer!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+macro_rules! float {
+    ($($ty: ty),*) => {
+        $(
+            impl FromValue for $ty {
+                fn ty() -> Type {
+                    Type::Float
+                }
+
+                fn from_value(value: Value) -> Option<Self> {
+                    value.as_float().map(|f| f as $ty)
+                }
+            }
+
+            impl IntoValue for $ty {
+                fn into_value(self) -> Result<Value, String> {
+                    Ok(Value::Float(self as f64))
+                }
+            }
+        )*
+    };
+}
+
+float!(f32, f64);
+
+impl FromValue for String {
+    fn ty() -> Type {
+        Type::Str
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self) -> Result<Value, String> {
+        Ok(Value::String(self))
+    }
+}
+
+impl IntoValue for &str {
+    fn into_value(self) -> Result<Value, String> {
+        Ok(Value::String(self.to_string()))
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn ty() -> Type {
+        Type::List(Some(Box::new(T::ty())))
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::List(items) => items.into_iter().map(T::from_value).collect(),
+            _ => None,
+        }
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self) -> Result<Value, String> {
+        let items: Result<Vec<Value>, String> = self.into_iter().map(T::into_value).collect();
+        items.map(Value::List)
+    }
+}
+
+/// Converts the values of a map, which both kinds of Rust map are made from
+fn map_from_value<T: FromValue, M: FromIterator<(String, T)>>(value: Value) -> Option<M> {
+    match value {
+        Value::Map(items) => items
+            .into_iter()
+            .map(|(key, value)| T::from_value(value).map(|value| (key, value)))
+            .collect(),
+        _ => None,
+    }
+}
+
+fn map_into_value<T: IntoValue>(
+    items: impl IntoIterator<Item = (String, T)>,
+) -> Result<Value, String> {
+    let items: Result<BTreeMap<String, Value>, String> = items
+        .into_iter()
+        .map(|(key, value)| value.into_value().map(|value| (key, value)))
+        .collect();
+    items.map(Value::Map)
+}
+
+impl<T: FromValue> FromValue for HashMap<String, T> {
+    fn ty() -> Type {
+        Type::Map(Some(Box::new(T::ty())))
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        map_from_value(value)
+    }
+}
+
+impl<T: IntoValue, S> IntoValue for HashMap<String, T, S> {
+    fn into_value(self) -> Result<Value, String> {
+        map_into_value(self)
+    }
+}
+
+impl<T: FromValue> FromValue for BTreeMap<String, T> {
+    fn ty() -> Type {
+        Type::Map(Some(Box::new(T::ty())))
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        map_from_value(value)
+    }
+}
+
+impl<T: IntoValue> IntoValue for BTreeMap<String, T> {
+    fn into_value(self) -> Result<Value, String> {
+        map_into_value(self)
+    }
+}
+
+/// `null` is `None`, and anything else must be a `T`
+impl<T: FromValue> FromValue for Option<T> {
+    fn ty() -> Type {
+        T::ty()
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::Null => Some(None),
+            value => T::from_value(value).map(Some),
+        }
+    }
+}
+
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(self) -> Result<Value, String> {
+        match self {
+            Some(value) => value.into_value(),
+            None => Ok(Value::Null),
+        }
+    }
+}
+
+/// An `Err` is raised as an error where the native proc was called
+impl<T: IntoValue, E: Display> IntoValue for Result<T, E> {
+    fn into_value(self) -> Result<Value, String> {
+        self.map_err(|e| e.to_string())?.into_value()
+    }
+}
+
+/// A Rust function that can be called from scripts, with `Args` being the
+/// tuple of its parameter types
+pub trait NativeFn<Args> {
+    /// The types of the function's parameters
+    fn params(&self) -> Vec<Type>;
+    /// Calls the function with exactly as many arguments as it has
+    /// parameters, converting them and checking their types first
+    fn call(&self, name: &str, args: Vec<Value>) -> Result<Value, String>;
+}
+
+/// Converts the argument at `index`, explaining what was expected if it is
+/// the wrong type
+fn argument<T: FromValue>(name: &str, index: usize, value: Value) -> Result<T, String> {
+    let type_name = value.type_name();
+    T::from_value(value).ok_or_else(|| {
+        format!(
+            "Argument {} of `{}` expects {}, got {}",
+            index + 1,
+            name,
+            T::ty(),
+            type_name
+        )
+    })
+}
+
+macro_rules! native_fn {
+    ($($arg: ident),*) => {
+        impl<F, R, $($arg: FromValue),*> NativeFn<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R,
+            R: IntoValue,
+        {
+            fn params(&self) -> Vec<Type> {
+                vec![$($arg::ty()),*]
+            }
+
+            #[allow(unused_variables, unused_mut)]
+            fn call(&self, name: &str, args: Vec<Value>) -> Result<Value, String> {
+                let mut args = args.into_iter().enumerate();
+                self($({
+                    let (index, value) = args.next().unwrap();
+                    argument::<$arg>(name, index, value)?
+                }),*)
+                .into_value()
+            }
+        }
+    };
+}
+
+native_fn!();
+native_fn!(A);
+native_fn!(A, B);
+native_fn!(A, B, C);
+native_fn!(A, B, C, D);
+native_fn!(A, B, C, D, E);
+native_fn!(A, B, C, D, E, G);
+
+/// Converts the arguments and calls the Rust function, given the proc's name
+type NativeCall = Box<dyn Fn(&str, Vec<Value>) -> Result<Value, String>>;
+
+/// A proc written in Rust, which scripts call like any other
+pub struct NativeProc {
+    pub name: Vec<String>,
+    pub params: Vec<Type>,
+    /// Shown by `help`, like a proc's doc comment
+    pub doc: Option<String>,
+    call: NativeCall,
+}
+
+impl NativeProc {
+    pub fn new<Args>(name: Vec<String>, f: impl NativeFn<Args> + 'static) -> Self {
+        Self {
+            name,
+            params: f.params(),
+            doc: None,
+            call: Box::new(move |name, args| f.call(name, args)),
+        }
+    }
+
+    pub fn with_doc(mut self, doc: &str) -> Self {
+        self.doc = Some(doc.to_string());
+        self
+    }
+
+    /// Calls the proc, which the caller has already checked the number of
+    /// arguments for
+    pub fn call(&self, args: Vec<Value>) -> Result<Value, String> {
+        (self.call)(&self.name.join("."), args)
+    }
+}
+
+impl Debug for NativeProc {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "NativeProc({})", self.name.join("."))
+    }
+}
+
+/// Native procs are only equal to themselves, as Rust closures can't be compared
+impl PartialEq for NativeProc {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+/// A namespace of native procs, registered all at once
+pub struct NativeModule {
+    name: Vec<String>,
+    procs: Vec<NativeProc>,
+}
+
+impl NativeModule {
+    /// A module for the namespace `name`, which may be nested like `host.io`
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.split('.').map(|part| part.to_string()).collect(),
+            procs: vec![],
+        }
+    }
+
+    /// Adds a native proc to the namespace
+    pub fn with_fn<Args>(self, name: &str, f: impl NativeFn<Args> + 'static) -> Self {
+        let mut path = self.name.clone();
+        path.push(name.to_string());
+        self.with_proc(NativeProc::new(path, f))
+    }
+
+    /// Adds a native proc with a doc comment, which `help` shows
+    pub fn with_documented_fn<Args>(
+        self,
+        name: &str,
+        doc: &str,
+        f: impl NativeFn<Args> + 'static,
+    ) -> Self {
+        let mut path = self.name.clone();
+        path.push(name.to_string());
+        self.with_proc(NativeProc::new(path, f).with_doc(doc))
+    }
+
+    /// Adds a native proc that was made with its full name
+    pub fn with_proc(mut self, native: NativeProc) -> Self {
+        self.procs.push(native);
+        self
+    }
+
+    pub fn into_procs(self) -> Vec<NativeProc> {
+        self.procs
+    }
+}
@@ -1,21 +1,33 @@
+use std::{collections::VecDeque, fmt::Display};
+
 use crate::{
-    error::error_at,
-    lexer::{Token, TWL},
+    error::{error_at, Error, ErrorKind},
+    lexer::{StringPart, Token, TWL},
+    limits::{stack_position, DEFAULT_MAX_STACK},
+    source::Span,
 };
 
+/// Type names that aren't keywords, so they are only types in annotations
+const TYPE_NAMES: [&str; 5] = ["float", "bigint", "any", "list", "map"];
+
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum LiteralType {
     String,
-    Number,
+    Integer,
+    Float,
+    BigInt,
     Boolean,
     Null,
 }
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum UnaryOperator {
     Negative,
     LogicalNot,
 }
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BinaryOperator {
     Add,
     Subtract,
@@ -31,6 +43,131 @@ pub enum BinaryOperator {
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Type {
+    Int,
+    Float,
+    BigInt,
+    Str,
+    Bool,
+    Any,
+    // List(element type)
+    List(Option<Box<Type>>),
+    // Map(value type)
+    Map(Option<Box<Type>>),
+    // Proc(Some((parameter types, return type))) or Proc(None) for any proc
+    Proc(Option<(Vec<Type>, Box<Type>)>),
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Float => write!(f, "float"),
+            Type::BigInt => write!(f, "bigint"),
+            Type::Str => write!(f, "str"),
+            Type::Bool => write!(f, "bool"),
+            Type::Any => write!(f, "any"),
+            Type::List(None) => write!(f, "list"),
+            Type::List(Some(t)) => write!(f, "list[{}]", t),
+            Type::Map(None) => write!(f, "map"),
+            Type::Map(Some(t)) => write!(f, "map[{}]", t),
+            Type::Proc(None) => write!(f, "proc"),
+            Type::Proc(Some((params, ret))) => {
+                let params: Vec<String> = params.iter().map(|p| p.to_string()).collect();
+                write!(f, "proc({}): {}", params.join(", "), ret)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Alignment {
+    Left,
+    Right,
+    Center,
+}
+
+/// How an interpolated value is formatted, written after a colon as
+/// `[[fill]align][0][width][.precision]`, such as `{price:>8.2}`
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FormatSpec {
+    pub fill: char,
+    // numbers are aligned right and everything else left by default
+    pub align: Option<Alignment>,
+    // pad numbers with zeros after the sign
+    pub zero: bool,
+    pub width: usize,
+    // decimal places for numbers, maximum length for everything else
+    pub precision: Option<usize>,
+}
+
+impl FormatSpec {
+    pub fn parse(spec: &str) -> Result<FormatSpec, String> {
+        let alignment = |c: &char| match c {
+            '<' => Some(Alignment::Left),
+            '>' => Some(Alignment::Right),
+            '^' => Some(Alignment::Center),
+            _ => None,
+        };
+        let chars: Vec<char> = spec.chars().collect();
+        let mut format = FormatSpec {
+            fill: ' ',
+            align: None,
+            zero: false,
+            width: 0,
+            precision: None,
+        };
+        let mut i = 0;
+        if let Some(align) = chars.get(1).and_then(alignment) {
+            format.fill = chars[0];
+            format.align = Some(align);
+            i = 2;
+        } else if let Some(align) = chars.first().and_then(alignment) {
+            format.align = Some(align);
+            i = 1;
+        }
+        if chars.get(i) == Some(&'0') {
+            format.zero = true;
+            i += 1;
+        }
+        let digits = |i: &mut usize| {
+            let start = *i;
+            while chars.get(*i).is_some_and(|c| c.is_ascii_digit()) {
+                *i += 1;
+            }
+            chars[start..*i]
+                .iter()
+                .collect::<String>()
+                .parse::<usize>()
+                .ok()
+        };
+        format.width = digits(&mut i).unwrap_or(0);
+        if chars.get(i) == Some(&'.') {
+            i += 1;
+            match digits(&mut i) {
+                Some(precision) => format.precision = Some(precision),
+                None => return Err(format!("Expected a precision after `.` in `{}`", spec)),
+            }
+        }
+        if i != chars.len() {
+            return Err(format!("Invalid format specifier `{}`", spec));
+        }
+        Ok(format)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum InterpolationPart {
+    Text(String),
+    Value(Box<ExprWL>, Option<FormatSpec>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Expr {
     Literal(LiteralType, String),
     Program(Vec<ExprWL>),
@@ -41,53 +178,73 @@ pub enum Expr {
     Call(Vec<String>, Vec<ExprWL>),
     // Import(Relative import?, path)
     Import(bool, String),
-    Proc(Vec<String>, Vec<String>, Vec<ExprWL>),
+    // Proc(name, [(parameter, type)], return type, body)
+    Proc(
+        Vec<String>,
+        Vec<(String, Option<Type>)>,
+        Option<Type>,
+        Vec<ExprWL>,
+    ),
     If(Box<ExprWL>, Vec<ExprWL>),
     For(Vec<String>, Box<ExprWL>, Box<ExprWL>, Vec<ExprWL>),
     While(Box<ExprWL>, Vec<ExprWL>),
     Return(Box<ExprWL>),
-    VariableDeclaration(Vec<String>, Box<ExprWL>),
-    ConstantDeclaration(Vec<String>, Box<ExprWL>),
+    VariableDeclaration(Vec<String>, Option<Type>, Box<ExprWL>),
+    ConstantDeclaration(Vec<String>, Option<Type>, Box<ExprWL>),
     VariableSet(Vec<String>, Box<ExprWL>),
     Namespace(Vec<String>, Vec<ExprWL>),
+    Interpolation(Vec<InterpolationPart>),
+    // Test(name, body), which only runs under `bull-script test`
+    Test(String, Vec<ExprWL>),
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ExprWL {
     pub expr: Expr,
-    pub linen: i32,
-    pub charn: i32,
-    pub filen: String,
+    pub span: Span,
+    /// The doc comment of a proc, namespace or const
+    pub doc: Option<String>,
 }
 
-#[derive(Clone)]
 pub struct Parser<'a> {
-    pub tokens: Vec<TWL<'a>>,
+    tokens: Box<dyn Iterator<Item = Result<TWL, Error>> + 'a>,
+    /// Tokens that have been peeked at but not eaten yet
+    lookahead: VecDeque<TWL>,
+    /// The error the lexer stopped at, which is raised again whenever the
+    /// parser looks past the last token before it
+    lex_error: Option<Error>,
+    /// The span of the last token eaten, where the expression being
+    /// parsed ends so far
+    previous: Option<Span>,
+    /// Where the stack was when parsing started, and how much of it nested
+    /// expressions may use
+    stack_start: usize,
+    max_stack: Option<usize>,
 }
 
 macro_rules! eat_token {
     ($self: ident) => {
-        &$self.tokens.remove(0)
+        &$self.next_token()?
     };
 }
 macro_rules! peek_token {
-    ($self: ident) => {{
-        let tok = &$self.tokens[0];
-
-        tok.clone()
-    }};
+    ($self: ident) => {
+        $self.peek_nth(0)?.clone()
+    };
 }
 
+/// Creates an expression spanning from the span `$s` to the last token eaten
 #[macro_export]
 macro_rules! ctwl {
-    ($t: expr, $s: expr) => {
+    ($self: ident, $t: expr, $s: expr) => {{
+        let start: Span = $s;
         ExprWL {
-            expr: $t.clone(),
-            linen: $s.clone().linen.clone(),
-            filen: $s.clone().filen.clone(),
-            charn: $s.clone().charn.clone(),
+            expr: $t,
+            span: $self.span_from(start),
+            doc: None,
         }
-    };
+    }};
 }
 
 /*
@@ -96,29 +253,114 @@ expression     → literal
                | binary
                | grouping ;
 
-literal        → NUMBER | STRING | "true" | "false" | "nil" ;
+literal        → INTEGER | FLOAT | BIGINT | STRING | "true" | "false" | "nil" ;
 grouping       → "(" expression ")" ;
 unary          → ( "-" | "!" ) expression ;
 binary         → expression operator expression ;
 operator       → "==" | "!=" | "<" | "<=" | ">" | ">="
-               | "+"  | "-"  | "*" | "/" ;
+               | "+"  | "-"  | "*" | "/" | "%" ;
  */
 
+impl<'a> Parser<'a> {
+    pub fn new(tokens: impl Iterator<Item = Result<TWL, Error>> + 'a) -> Self {
+        Parser {
+            tokens: Box::new(tokens),
+            lookahead: VecDeque::new(),
+            lex_error: None,
+            previous: None,
+            stack_start: stack_position(),
+            max_stack: Some(DEFAULT_MAX_STACK),
+        }
+    }
+
+    /// Limits the bytes of the stack used by parsing code nested in itself,
+    /// like `Limits::with_max_stack` does for running it, or takes the
+    /// limit away with `None`
+    pub fn with_max_stack(mut self, bytes: Option<usize>) -> Self {
+        self.max_stack = bytes;
+        self
+    }
+}
+
 impl Parser<'_> {
-    pub fn parse_expression(&mut self) -> ExprWL {
+    /// The token `n` places ahead, pulling tokens from the lexer as
+    /// needed. Past the end this is the EOF token, and past a token the
+    /// lexer couldn't read it is the lexer's error.
+    fn peek_nth(&mut self, n: usize) -> Result<&TWL, Error> {
+        while self.lookahead.len() <= n {
+            if let Some(error) = &self.lex_error {
+                return Err(error.clone());
+            }
+            let token = match self.tokens.next() {
+                Some(Ok(token)) => token,
+                Some(Err(error)) => {
+                    self.lex_error = Some(error);
+                    continue;
+                }
+                None => self
+                    .lookahead
+                    .back()
+                    .cloned()
+                    .expect("Unreachable: the lexer always ends with EOF"),
+            };
+            self.lookahead.push_back(token);
+        }
+        Ok(&self.lookahead[n])
+    }
+
+    /// The token `n` places ahead, for looking ahead without raising the
+    /// lexer's errors, which are raised once the token is eaten
+    fn peek_token_at(&mut self, n: usize) -> Option<&Token> {
+        self.peek_nth(n).ok().map(|t| &t.token)
+    }
+
+    /// Eats the next token, leaving EOF in place once it is reached
+    fn next_token(&mut self) -> Result<TWL, Error> {
+        if let Token::EOF() = self.peek_nth(0)?.token {
+            return Ok(self.lookahead[0].clone());
+        }
+        let token = self
+            .lookahead
+            .pop_front()
+            .expect("Unreachable: a token was just peeked");
+        self.previous = Some(token.span.clone());
+        Ok(token)
+    }
+
+    /// The span from `start` to the end of the last token eaten
+    fn span_from(&self, start: Span) -> Span {
+        match &self.previous {
+            Some(previous) => start.to(previous),
+            None => start,
+        }
+    }
+
+    /// Parses an expression, which is nested in the one being parsed when
+    /// there is one
+    pub fn parse_expression(&mut self) -> Result<ExprWL, Error> {
+        if let Some(max) = self.max_stack {
+            if stack_position().abs_diff(self.stack_start) > max {
+                let mut error = error_at(
+                    self.peek_nth(0)?.span.clone(),
+                    &format!("Nested too deep for {} bytes of stack", max),
+                );
+                error.kind = ErrorKind::CallDepth;
+                return Err(error);
+            }
+        }
+        self.expression()
+    }
+
+    fn expression(&mut self) -> Result<ExprWL, Error> {
         let peek = peek_token!(self);
         if let Token::ImportKeyword() = &peek.token.clone() {
             let peek = eat_token!(self);
             let path = eat_token!(self);
             match path.token.clone() {
-                Token::StringLiteral(str) => return ctwl!(Expr::Import(true, str), peek),
-                Token::Identifier(vec) => return ctwl!(Expr::Import(false, vec.join(".")), peek),
-                _ => error_at(
-                    &peek.filen,
-                    &peek.linen,
-                    &peek.charn,
-                    &format!("Expected string literal or identifier after import statement, instead found {:?}", path.token),
-                ),
+                Token::StringLiteral(str) => return Ok(ctwl!(self, Expr::Import(true, str), peek.span.clone())),
+                Token::Identifier(vec) => return Ok(ctwl!(self, Expr::Import(false, vec.join(".")), peek.span.clone())),
+                _ => return Err(error_at(peek.span.clone(), &format!("Expected string literal or identifier after import statement, instead found {:?}", path.token),
+                )),
             }
         }
 
@@ -130,12 +372,10 @@ impl Parser<'_> {
                 if let Token::Identifier(ve) = then.token.clone() {
                     ve
                 } else {
-                    error_at(
-                        &then.filen,
-                        &then.linen,
-                        &then.charn,
+                    return Err(error_at(
+                        then.span.clone(),
                         &format!("Expected Identifier, got {:?}", then.token),
-                    )
+                    ));
                 }
             };
 
@@ -149,17 +389,21 @@ impl Parser<'_> {
                     break;
                 }
                 if let Token::EOF() = key.token {
-                    error_at(
-                        &key.filen,
-                        &key.linen,
-                        &key.charn,
+                    return Err(error_at(
+                        key.span,
                         &format!("Prematurely reached EOF, did you end your proc?"),
-                    )
+                    ));
                 }
-                program.push(self.parse_expression());
+                program.push(self.parse_expression()?);
                 key = peek_token!(self);
             }
-            return ctwl!(Expr::Namespace(nmspc_name, program), peek);
+            let mut namespace = ctwl!(
+                self,
+                Expr::Namespace(nmspc_name, program),
+                peek.span.clone()
+            );
+            namespace.doc = peek.doc.clone();
+            return Ok(namespace);
         }
 
         if let Token::Let() = peek.token.clone() {
@@ -169,28 +413,29 @@ impl Parser<'_> {
                 if let Token::Identifier(ve) = then.token.clone() {
                     ve
                 } else {
-                    error_at(
-                        &then.filen,
-                        &then.linen,
-                        &then.charn,
+                    return Err(error_at(
+                        then.span.clone(),
                         &format!("Expected Identifier, got {:?}", then.token),
-                    )
+                    ));
                 }
             };
+            let vartype = self.parse_annotation()?;
             _ = {
                 let then = eat_token!(self);
                 if let Token::OperatorSet() = then.token.clone() {
                 } else {
-                    error_at(
-                        &then.filen,
-                        &then.linen,
-                        &then.charn,
+                    return Err(error_at(
+                        then.span.clone(),
                         &format!("Expected \"=\", got {:?}", then.token),
-                    )
+                    ));
                 }
             };
-            let expr = self.parse_expression().clone();
-            return ctwl!(Expr::VariableDeclaration(varname, Box::new(expr)), peek);
+            let expr = self.parse_expression()?;
+            return Ok(ctwl!(
+                self,
+                Expr::VariableDeclaration(varname, vartype, Box::new(expr)),
+                peek.span.clone()
+            ));
         }
         if let Token::Const() = peek.token.clone() {
             _ = eat_token!(self);
@@ -199,45 +444,46 @@ impl Parser<'_> {
                 if let Token::Identifier(ve) = then.token.clone() {
                     ve
                 } else {
-                    error_at(
-                        &then.filen,
-                        &then.linen,
-                        &then.charn,
+                    return Err(error_at(
+                        then.span.clone(),
                         &format!("Expected Identifier, got {:?}", then.token),
-                    )
+                    ));
                 }
             };
+            let vartype = self.parse_annotation()?;
             _ = {
                 let then = eat_token!(self);
                 if let Token::OperatorSet() = then.token.clone() {
                 } else {
-                    error_at(
-                        &then.filen,
-                        &then.linen,
-                        &then.charn,
+                    return Err(error_at(
+                        then.span.clone(),
                         &format!("Expected \"=\", got {:?}", then.token),
-                    )
+                    ));
                 }
             };
-            let expr = self.parse_expression().clone();
-            return ctwl!(Expr::ConstantDeclaration(varname, Box::new(expr)), peek);
+            let expr = self.parse_expression()?;
+            let mut constant = ctwl!(
+                self,
+                Expr::ConstantDeclaration(varname, vartype, Box::new(expr)),
+                peek.span.clone()
+            );
+            constant.doc = peek.doc.clone();
+            return Ok(constant);
         }
 
         if let Token::If() = peek.token.clone() {
             _ = eat_token!(self);
 
-            let expr = self.parse_expression();
+            let expr = self.parse_expression()?;
 
             // get body of program
             {
                 let then = eat_token!(self);
-                if !matches!(then.token, Token::Then(..)) {
-                    error_at(
-                        &then.filen,
-                        &then.linen,
-                        &then.charn,
+                if !matches!(then.token, Token::Then(..) | Token::Colon(..)) {
+                    return Err(error_at(
+                        then.span.clone(),
                         &format!("Expected \"then\" keyword, got {:?}", then.token),
-                    )
+                    ));
                 };
             }
             let mut key = peek_token!(self);
@@ -248,17 +494,19 @@ impl Parser<'_> {
                     break;
                 }
                 if let Token::EOF() = key.token {
-                    error_at(
-                        &key.filen,
-                        &key.linen,
-                        &key.charn,
+                    return Err(error_at(
+                        key.span,
                         &format!("Prematurely reached EOF, did you end your proc?"),
-                    )
+                    ));
                 }
-                program.push(self.parse_expression());
+                program.push(self.parse_expression()?);
                 key = peek_token!(self);
             }
-            return ctwl!(Expr::If(Box::new(expr), program), peek);
+            return Ok(ctwl!(
+                self,
+                Expr::If(Box::new(expr), program),
+                peek.span.clone()
+            ));
         }
         if let Token::For() = peek.token.clone() {
             _ = eat_token!(self);
@@ -268,12 +516,10 @@ impl Parser<'_> {
                 if let Token::Identifier(ve) = then.token.clone() {
                     ve
                 } else {
-                    error_at(
-                        &then.filen,
-                        &then.linen,
-                        &then.charn,
+                    return Err(error_at(
+                        then.span.clone(),
                         &format!("Expected Identifier, got {:?}", then.token),
-                    )
+                    ));
                 }
             };
 
@@ -281,81 +527,35 @@ impl Parser<'_> {
                 let then = eat_token!(self);
                 if let Token::OperatorSet() = then.token.clone() {
                 } else {
-                    error_at(
-                        &then.filen,
-                        &then.linen,
-                        &then.charn,
+                    return Err(error_at(
+                        then.span.clone(),
                         &format!("Expected \"=\", got {:?}", then.token),
-                    )
+                    ));
                 }
             };
 
-            let startval = ctwl!(
-                {
-                    let then = eat_token!(self);
-                    if let Token::NumericLiteral(f, _s) = then.token.clone() {
-                        Expr::Literal(LiteralType::Number, f.to_string())
-                    } else if let Token::Identifier(ve) = then.token.clone() {
-                        Expr::Identifier(ve)
-                    } else {
-                        error_at(
-                            &then.filen,
-                            &then.linen,
-                            &then.charn,
-                            &format!(
-                                "Expected Identifier or numeric literal, got {:?}",
-                                then.token
-                            ),
-                        )
-                    }
-                },
-                peek
-            );
+            let startval = self.for_bound()?;
 
             _ = {
                 let then = eat_token!(self);
                 if let Token::To() = then.token.clone() {
                 } else {
-                    error_at(
-                        &then.filen,
-                        &then.linen,
-                        &then.charn,
+                    return Err(error_at(
+                        then.span.clone(),
                         &format!("Expected \"to\", got {:?}", then.token),
-                    )
+                    ));
                 }
             };
-            let endval = ctwl!(
-                {
-                    let then = eat_token!(self);
-                    if let Token::NumericLiteral(f, _s) = then.token.clone() {
-                        Expr::Literal(LiteralType::Number, f.to_string())
-                    } else if let Token::Identifier(ve) = then.token.clone() {
-                        Expr::Identifier(ve)
-                    } else {
-                        error_at(
-                            &then.filen,
-                            &then.linen,
-                            &then.charn,
-                            &format!(
-                                "Expected Identifier or numeric literal, got {:?}",
-                                then.token
-                            ),
-                        )
-                    }
-                },
-                peek
-            );
+            let endval = self.for_bound()?;
 
             // get body of program
             {
                 let then = eat_token!(self);
-                if !matches!(then.token, Token::Then(..)) {
-                    error_at(
-                        &then.filen,
-                        &then.linen,
-                        &then.charn,
+                if !matches!(then.token, Token::Then(..) | Token::Colon(..)) {
+                    return Err(error_at(
+                        then.span.clone(),
                         &format!("Expected \"then\" keyword, got {:?}", then.token),
-                    )
+                    ));
                 };
             }
             let mut key = peek_token!(self);
@@ -366,36 +566,33 @@ impl Parser<'_> {
                     break;
                 }
                 if let Token::EOF() = key.token {
-                    error_at(
-                        &key.filen,
-                        &key.linen,
-                        &key.charn,
+                    return Err(error_at(
+                        key.span,
                         &format!("Prematurely reached EOF, did you end your proc?"),
-                    )
+                    ));
                 }
-                program.push(self.parse_expression());
+                program.push(self.parse_expression()?);
                 key = peek_token!(self);
             }
-            return ctwl!(
+            return Ok(ctwl!(
+                self,
                 Expr::For(varname, Box::new(startval), Box::new(endval), program),
-                peek
-            );
+                peek.span.clone()
+            ));
         }
         if let Token::While() = peek.token.clone() {
             _ = eat_token!(self);
 
-            let expr = self.parse_expression();
+            let expr = self.parse_expression()?;
 
             // get body of program
             {
                 let then = eat_token!(self);
-                if !matches!(then.token, Token::Then(..)) {
-                    error_at(
-                        &then.filen,
-                        &then.linen,
-                        &then.charn,
+                if !matches!(then.token, Token::Then(..) | Token::Colon(..)) {
+                    return Err(error_at(
+                        then.span.clone(),
                         &format!("Expected \"then\" keyword, got {:?}", then.token),
-                    )
+                    ));
                 };
             }
             let mut key = peek_token!(self);
@@ -406,235 +603,555 @@ impl Parser<'_> {
                     break;
                 }
                 if let Token::EOF() = key.token {
-                    error_at(
-                        &key.filen,
-                        &key.linen,
-                        &key.charn,
+                    return Err(error_at(
+                        key.span,
                         &format!("Prematurely reached EOF, did you end your proc?"),
-                    )
+                    ));
                 }
-                program.push(self.parse_expression());
+                program.push(self.parse_expression()?);
                 key = peek_token!(self);
             }
-            return ctwl!(Expr::While(Box::new(expr), program), peek);
+            return Ok(ctwl!(
+                self,
+                Expr::While(Box::new(expr), program),
+                peek.span.clone()
+            ));
+        }
+        if let Some(test_name) = self.test_name(&peek) {
+            _ = eat_token!(self);
+            _ = eat_token!(self);
+
+            // get body of test
+            {
+                let then = eat_token!(self);
+                if !matches!(then.token, Token::Then(..) | Token::Colon(..)) {
+                    return Err(error_at(
+                        then.span.clone(),
+                        &format!("Expected \"then\" keyword, got {:?}", then.token),
+                    ));
+                };
+            }
+            let mut key = peek_token!(self);
+            let mut program: Vec<ExprWL> = vec![];
+            loop {
+                if let Token::End() = key.token {
+                    _ = eat_token!(self);
+                    break;
+                }
+                if let Token::EOF() = key.token {
+                    return Err(error_at(
+                        key.span,
+                        "Prematurely reached EOF, did you end your test?",
+                    ));
+                }
+                program.push(self.parse_expression()?);
+                key = peek_token!(self);
+            }
+            return Ok(ctwl!(
+                self,
+                Expr::Test(test_name, program),
+                peek.span.clone()
+            ));
         }
         if let Token::Return() = peek.token.clone() {
             _ = eat_token!(self);
-            let expr = self.parse_expression();
-            return ctwl!(Expr::Return(Box::new(expr)), peek);
+            let expr = self.parse_expression()?;
+            return Ok(ctwl!(self, Expr::Return(Box::new(expr)), peek.span.clone()));
+        }
+        self.equality()
+    }
+    /// The name of the test `peek` starts, if it does. `test` is only a
+    /// keyword when a test's name follows it on the same line, so it can
+    /// still be used as a name.
+    fn test_name(&mut self, peek: &TWL) -> Option<String> {
+        if !matches!(&peek.token, Token::Identifier(name) if name == &["test"]) {
+            return None;
+        }
+        let line = |span: &Span| span.line_col().map(|(line, _)| line);
+        match self.peek_nth(1).ok()? {
+            TWL {
+                token: Token::StringLiteral(name),
+                span,
+                ..
+            } if line(span) == line(&peek.span) => Some(name.clone()),
+            _ => None,
         }
-        return self.equality();
     }
-    pub fn parse_program(&mut self) -> ExprWL {
+    pub fn parse_program(&mut self) -> Result<ExprWL, Error> {
+        let start = peek_token!(self).span;
         let mut l: Vec<ExprWL> = vec![];
-        while self.tokens.len() > 1 {
-            let expr = self.parse_expression();
+        while !matches!(peek_token!(self).token, Token::EOF()) {
+            let expr = self.parse_expression()?;
             l.push(expr);
         }
-        return ctwl!(Expr::Program(l), self.tokens[0]);
+        Ok(ctwl!(self, Expr::Program(l), start))
+    }
+    /// Parses an optional `: type` annotation on a variable or parameter
+    fn parse_annotation(&mut self) -> Result<Option<Type>, Error> {
+        if !matches!(peek_token!(self).token, Token::Colon()) || !self.is_type_at(1) {
+            return Ok(None);
+        }
+        _ = eat_token!(self);
+        Ok(Some(self.parse_type()?))
+    }
+    /// Parses an optional `: type` annotation on what a proc returns.
+    ///
+    /// `:` can also be used in place of `then`, so the colon is only an
+    /// annotation when the type after it is followed by `then` or `:`.
+    fn parse_return_annotation(&mut self) -> Result<Option<Type>, Error> {
+        if !matches!(peek_token!(self).token, Token::Colon()) || !self.is_return_type_at(1) {
+            return Ok(None);
+        }
+        _ = eat_token!(self);
+        Ok(Some(self.parse_type()?))
+    }
+    /// Whether the token `n` ahead starts a proc's return type, which is a
+    /// type followed by `then` or `:`
+    pub(crate) fn is_return_type_at(&mut self, n: usize) -> bool {
+        match self.skip_type(n) {
+            Some(end) => matches!(
+                self.peek_token_at(end),
+                Some(Token::Then() | Token::Colon())
+            ),
+            None => false,
+        }
+    }
+    /// Whether the token `n` ahead starts a type. Only `int`, `str` and
+    /// `bool` are keywords, so the other type names can still be used as
+    /// names outside of annotations.
+    fn is_type_at(&mut self, n: usize) -> bool {
+        match self.peek_token_at(n) {
+            Some(Token::BuiltinType(_) | Token::Proc()) => true,
+            Some(Token::Identifier(parts)) => {
+                parts.len() == 1 && TYPE_NAMES.contains(&parts[0].as_str())
+            }
+            _ => false,
+        }
+    }
+    /// Looks ahead past the type starting `n` tokens ahead without parsing
+    /// it, giving how far ahead the token after it is
+    fn skip_type(&mut self, n: usize) -> Option<usize> {
+        if !self.is_type_at(n) {
+            return None;
+        }
+        let start = self.peek_token_at(n)?.clone();
+        let mut n = n + 1;
+        match start {
+            Token::Identifier(parts) if parts == ["list"] || parts == ["map"] => {
+                if matches!(self.peek_token_at(n), Some(Token::OpenSquare())) {
+                    n = self.skip_type(n + 1)?;
+                    if !matches!(self.peek_token_at(n), Some(Token::CloseSquare())) {
+                        return None;
+                    }
+                    n += 1;
+                }
+            }
+            Token::Proc() if matches!(self.peek_token_at(n), Some(Token::OpenParen())) => {
+                n += 1;
+                while !matches!(self.peek_token_at(n), Some(Token::CloseParen())) {
+                    n = self.skip_type(n)?;
+                    if matches!(self.peek_token_at(n), Some(Token::Comma())) {
+                        n += 1;
+                    }
+                }
+                n += 1;
+                if matches!(self.peek_token_at(n), Some(Token::Colon())) {
+                    if let Some(end) = self.skip_type(n + 1) {
+                        n = end;
+                    }
+                }
+            }
+            _ => {}
+        }
+        Some(n)
+    }
+    fn parse_type(&mut self) -> Result<Type, Error> {
+        let t = eat_token!(self);
+        Ok(match t.token.clone() {
+            Token::BuiltinType(name) if name == "int" => Type::Int,
+            Token::BuiltinType(name) if name == "str" => Type::Str,
+            Token::BuiltinType(name) if name == "bool" => Type::Bool,
+            Token::Identifier(parts) if parts == ["float"] => Type::Float,
+            Token::Identifier(parts) if parts == ["bigint"] => Type::BigInt,
+            Token::Identifier(parts) if parts == ["any"] => Type::Any,
+            Token::Identifier(parts) if parts == ["list"] => {
+                Type::List(self.parse_type_parameter()?)
+            }
+            Token::Identifier(parts) if parts == ["map"] => Type::Map(self.parse_type_parameter()?),
+            Token::Proc() => {
+                if !matches!(peek_token!(self).token, Token::OpenParen()) {
+                    return Ok(Type::Proc(None));
+                }
+                _ = eat_token!(self);
+                let mut params: Vec<Type> = vec![];
+                loop {
+                    if let Token::CloseParen() = peek_token!(self).token {
+                        _ = eat_token!(self);
+                        break;
+                    }
+                    params.push(self.parse_type()?);
+                    if let Token::Comma() = peek_token!(self).token {
+                        _ = eat_token!(self);
+                    }
+                }
+                let ret = self.parse_annotation()?.unwrap_or(Type::Any);
+                Type::Proc(Some((params, Box::new(ret))))
+            }
+            _ => {
+                return Err(error_at(
+                    t.span.clone(),
+                    &format!("Expected a type, got {:?}", t.token),
+                ))
+            }
+        })
+    }
+    /// Parses the optional `[type]` after `list` or `map`
+    fn parse_type_parameter(&mut self) -> Result<Option<Box<Type>>, Error> {
+        if !matches!(peek_token!(self).token, Token::OpenSquare()) {
+            return Ok(None);
+        }
+        _ = eat_token!(self);
+        let inner = self.parse_type()?;
+        let close = eat_token!(self);
+        if !matches!(close.token, Token::CloseSquare()) {
+            return Err(error_at(
+                close.span.clone(),
+                &format!("Expected \"]\", got {:?}", close.token),
+            ));
+        }
+        Ok(Some(Box::new(inner)))
     }
     // MATH
-    fn equality(&mut self) -> ExprWL {
-        let mut expr = self.comparison();
+    fn interpolation_part(
+        &self,
+        part: &StringPart,
+        string: &TWL,
+    ) -> Result<InterpolationPart, Error> {
+        Ok(match part {
+            StringPart::Text(text) => InterpolationPart::Text(text.clone()),
+            StringPart::Value(tokens, spec) => {
+                let mut tokens = tokens.clone();
+                let last = tokens.last().unwrap_or(string).clone();
+                tokens.push(TWL {
+                    token: Token::EOF(),
+                    ..last
+                });
+                // the expression is nested in the string, so it shares the
+                // stack this parser may use
+                let mut parser = Parser::new(tokens.into_iter().map(Ok));
+                parser.stack_start = self.stack_start;
+                parser.max_stack = self.max_stack;
+                let value = parser.parse_expression()?;
+                let next = peek_token!(parser);
+                if !matches!(next.token, Token::EOF()) {
+                    return Err(error_at(
+                        next.span,
+                        &format!("Unexpected {:?} in interpolated expression", next.token),
+                    ));
+                }
+                let spec = match spec {
+                    Some(spec) => Some(
+                        FormatSpec::parse(spec)
+                            .map_err(|reason| error_at(string.span.clone(), &reason))?,
+                    ),
+                    None => None,
+                };
+                InterpolationPart::Value(Box::new(value), spec)
+            }
+        })
+    }
+    /// Parses the start or end of a for loop, spanning just its own token
+    fn for_bound(&mut self) -> Result<ExprWL, Error> {
+        let then = eat_token!(self);
+        let expr = match then.token.clone() {
+            Token::IntegerLiteral(i) => Expr::Literal(LiteralType::Integer, i.to_string()),
+            Token::FloatLiteral(f) => Expr::Literal(LiteralType::Float, f.to_string()),
+            Token::BigIntLiteral(d) => Expr::Literal(LiteralType::BigInt, d),
+            Token::Identifier(ve) => Expr::Identifier(ve),
+            _ => {
+                return Err(error_at(
+                    then.span.clone(),
+                    &format!(
+                        "Expected Identifier or numeric literal, got {:?}",
+                        then.token
+                    ),
+                ))
+            }
+        };
+        Ok(ExprWL {
+            expr,
+            span: then.span.clone(),
+            doc: None,
+        })
+    }
+    fn equality(&mut self) -> Result<ExprWL, Error> {
+        let mut expr = self.comparison()?;
         loop {
             match peek_token!(self).token {
                 Token::OperatorEquals() => {
                     let _tmp = eat_token!(self);
-                    let right = self.comparison();
+                    let right = self.comparison()?;
                     expr = ctwl!(
-                        Expr::Binary(
-                            BinaryOperator::Equal,
-                            Box::new(expr.clone()),
-                            Box::new(right)
-                        ),
-                        expr
+                        self,
+                        Expr::Binary(BinaryOperator::Equal, Box::new(expr), Box::new(right)),
+                        expr.span.clone()
                     );
                 }
                 Token::OperatorNotEquals() => {
                     _ = eat_token!(self).token.clone();
-                    let right = self.comparison();
+                    let right = self.comparison()?;
                     expr = ctwl!(
-                        Expr::Binary(
-                            BinaryOperator::NotEqual,
-                            Box::new(expr.clone()),
-                            Box::new(right)
-                        ),
-                        expr.clone()
+                        self,
+                        Expr::Binary(BinaryOperator::NotEqual, Box::new(expr), Box::new(right)),
+                        expr.span.clone()
                     );
                 }
                 _ => break,
             }
         }
-        return expr;
+        return Ok(expr);
     }
-    fn comparison(&mut self) -> ExprWL {
-        let mut expr = self.term();
+    fn comparison(&mut self) -> Result<ExprWL, Error> {
+        let mut expr = self.term()?;
         loop {
             match peek_token!(self).token {
                 Token::OperatorGreater() => {
                     _ = eat_token!(self).token.clone();
-                    let right = self.term();
+                    let right = self.term()?;
                     expr = ctwl!(
-                        Expr::Binary(
-                            BinaryOperator::Greater,
-                            Box::new(expr.clone()),
-                            Box::new(right)
-                        ),
-                        expr
+                        self,
+                        Expr::Binary(BinaryOperator::Greater, Box::new(expr), Box::new(right)),
+                        expr.span.clone()
                     );
                 }
                 Token::OperatorLesser() => {
                     _ = eat_token!(self).token.clone();
-                    let right = self.term();
+                    let right = self.term()?;
                     expr = ctwl!(
-                        Expr::Binary(
-                            BinaryOperator::Lesser,
-                            Box::new(expr.clone()),
-                            Box::new(right)
-                        ),
-                        expr
+                        self,
+                        Expr::Binary(BinaryOperator::Lesser, Box::new(expr), Box::new(right)),
+                        expr.span.clone()
                     );
                 }
                 Token::OperatorLesserEqual() => {
                     _ = eat_token!(self).token.clone();
-                    let right = self.term();
+                    let right = self.term()?;
                     expr = ctwl!(
-                        Expr::Binary(
-                            BinaryOperator::Lesser,
-                            Box::new(expr.clone()),
-                            Box::new(right)
-                        ),
-                        expr
+                        self,
+                        Expr::Binary(BinaryOperator::LesserEqual, Box::new(expr), Box::new(right)),
+                        expr.span.clone()
                     );
                 }
                 Token::OperatorGreaterEqual() => {
                     _ = eat_token!(self).token.clone();
-                    let right = self.term();
+                    let right = self.term()?;
                     expr = ctwl!(
+                        self,
                         Expr::Binary(
-                            BinaryOperator::Lesser,
-                            Box::new(expr.clone()),
+                            BinaryOperator::GreaterEqual,
+                            Box::new(expr),
                             Box::new(right)
                         ),
-                        expr
+                        expr.span.clone()
                     );
                 }
                 _ => break,
             }
         }
-        return expr;
+        return Ok(expr);
     }
-    fn term(&mut self) -> ExprWL {
-        let mut expr = self.factor();
+    fn term(&mut self) -> Result<ExprWL, Error> {
+        let mut expr = self.factor()?;
         loop {
             match peek_token!(self).token {
                 Token::OperatorSubtract() => {
                     _ = eat_token!(self).token.clone();
-                    let right = self.factor();
+                    let right = self.factor()?;
                     expr = ctwl!(
-                        Expr::Binary(
-                            BinaryOperator::Subtract,
-                            Box::new(expr.clone()),
-                            Box::new(right)
-                        ),
-                        expr
+                        self,
+                        Expr::Binary(BinaryOperator::Subtract, Box::new(expr), Box::new(right)),
+                        expr.span.clone()
                     );
                 }
                 Token::OperatorAdd() => {
                     _ = eat_token!(self).token.clone();
-                    let right = self.factor();
+                    let right = self.factor()?;
                     expr = ctwl!(
-                        Expr::Binary(BinaryOperator::Add, Box::new(expr.clone()), Box::new(right)),
-                        expr
+                        self,
+                        Expr::Binary(BinaryOperator::Add, Box::new(expr), Box::new(right)),
+                        expr.span.clone()
                     );
                 }
                 _ => break,
             }
         }
-        return expr;
+        return Ok(expr);
     }
-    fn factor(&mut self) -> ExprWL {
-        let mut expr = self.unary();
+    fn factor(&mut self) -> Result<ExprWL, Error> {
+        let mut expr = self.unary()?;
         loop {
             match peek_token!(self).token {
                 Token::OperatorMultiply() => {
                     let _temp = eat_token!(self).token.clone();
-                    let right = self.unary();
+                    let right = self.unary()?;
                     expr = ctwl!(
-                        Expr::Binary(
-                            BinaryOperator::Multiply,
-                            Box::new(expr.clone()),
-                            Box::new(right)
-                        ),
-                        expr.clone()
+                        self,
+                        Expr::Binary(BinaryOperator::Multiply, Box::new(expr), Box::new(right)),
+                        expr.span.clone()
                     );
                 }
                 Token::OperatorDivide() => {
                     _ = eat_token!(self).token.clone();
-                    let right = self.unary();
+                    let right = self.unary()?;
                     expr = ctwl!(
-                        Expr::Binary(
-                            BinaryOperator::Divide,
-                            Box::new(expr.clone()),
-                            Box::new(right)
-                        ),
-                        expr.clone()
+                        self,
+                        Expr::Binary(BinaryOperator::Divide, Box::new(expr), Box::new(right)),
+                        expr.span.clone()
+                    );
+                }
+                Token::OperatorMod() => {
+                    _ = eat_token!(self).token.clone();
+                    let right = self.unary()?;
+                    expr = ctwl!(
+                        self,
+                        Expr::Binary(BinaryOperator::Mod, Box::new(expr), Box::new(right)),
+                        expr.span.clone()
                     );
                 }
                 _ => break,
             }
         }
-        return expr;
+        return Ok(expr);
     }
-    fn unary(&mut self) -> ExprWL {
+    fn unary(&mut self) -> Result<ExprWL, Error> {
         match peek_token!(self).token {
             Token::OperatorSubtract() => {
-                _ = eat_token!(self).token.clone();
-                let right = self.primary();
-                return ctwl!(
-                    Expr::Unary(UnaryOperator::Negative, Box::new(right.clone())),
-                    right.clone()
-                );
+                let start = eat_token!(self).span.clone();
+                let right = self.primary()?;
+                return Ok(ctwl!(
+                    self,
+                    Expr::Unary(UnaryOperator::Negative, Box::new(right)),
+                    start
+                ));
             }
             Token::OperatorLogicalNot() => {
-                _ = eat_token!(self).token.clone();
-                let right = self.primary();
-                return ctwl!(
-                    Expr::Unary(UnaryOperator::LogicalNot, Box::new(right.clone())),
-                    right.clone()
-                );
+                let start = eat_token!(self).span.clone();
+                let right = self.primary()?;
+                return Ok(ctwl!(
+                    self,
+                    Expr::Unary(UnaryOperator::LogicalNot, Box::new(right)),
+                    start
+                ));
             }
             _ => {}
         }
         return self.primary();
     }
-    fn primary(&mut self) -> ExprWL {
+    fn primary(&mut self) -> Result<ExprWL, Error> {
         let p = eat_token!(self);
 
         if let Token::StringLiteral(str) = &p.token {
-            return ctwl!(Expr::Literal(LiteralType::String, str.to_string()), p);
+            return Ok(ctwl!(
+                self,
+                Expr::Literal(LiteralType::String, str.to_string()),
+                p.span.clone()
+            ));
+        }
+        if let Token::CharLiteral(c) = &p.token {
+            // there is no char type, so characters are one character strings
+            return Ok(ctwl!(
+                self,
+                Expr::Literal(LiteralType::String, c.to_string()),
+                p.span.clone()
+            ));
+        }
+        if let Token::InterpolatedString(parts) = &p.token {
+            let parts = parts
+                .iter()
+                .map(|part| self.interpolation_part(part, p))
+                .collect::<Result<_, _>>()?;
+            return Ok(ctwl!(self, Expr::Interpolation(parts), p.span.clone()));
+        }
+        if let Token::IntegerLiteral(num) = &p.token {
+            return Ok(ctwl!(
+                self,
+                Expr::Literal(LiteralType::Integer, num.to_string()),
+                p.span.clone()
+            ));
         }
-        if let Token::NumericLiteral(num, _) = &p.token {
-            return ctwl!(Expr::Literal(LiteralType::Number, num.to_string()), p);
+        if let Token::FloatLiteral(num) = &p.token {
+            return Ok(ctwl!(
+                self,
+                Expr::Literal(LiteralType::Float, num.to_string()),
+                p.span.clone()
+            ));
+        }
+        if let Token::BigIntLiteral(digits) = &p.token {
+            return Ok(ctwl!(
+                self,
+                Expr::Literal(LiteralType::BigInt, digits.clone()),
+                p.span.clone()
+            ));
+        }
+        if let Token::BuiltinType(name) = &p.token {
+            // conversion functions, such as `int(x)`
+            if let Token::OpenParen() = peek_token!(self).token {
+                _ = eat_token!(self);
+                let mut arguments: Vec<ExprWL> = vec![];
+                loop {
+                    if let Token::CloseParen() = peek_token!(self).token {
+                        _ = eat_token!(self);
+                        break;
+                    }
+                    let expr = self.parse_expression()?;
+                    arguments.push(expr);
+                    if let Token::Comma() = peek_token!(self).token {
+                        _ = eat_token!(self);
+                        continue;
+                    }
+                }
+                return Ok(ctwl!(
+                    self,
+                    Expr::Call(vec![name.to_string()], arguments),
+                    p.span.clone()
+                ));
+            }
         }
         if let Token::BooleanLiteral(b) = &p.token {
-            return if b.clone() {
-                ctwl!(Expr::Literal(LiteralType::Boolean, "1".to_string()), p)
+            return Ok(if b.clone() {
+                ctwl!(
+                    self,
+                    Expr::Literal(LiteralType::Boolean, "1".to_string()),
+                    p.span.clone()
+                )
             } else {
-                ctwl!(Expr::Literal(LiteralType::Boolean, "0".to_string()), p)
-            };
+                ctwl!(
+                    self,
+                    Expr::Literal(LiteralType::Boolean, "0".to_string()),
+                    p.span.clone()
+                )
+            });
+        }
+        if let Token::NullLiteral() = &p.token {
+            return Ok(ctwl!(
+                self,
+                Expr::Literal(LiteralType::Null, String::new()),
+                p.span.clone()
+            ));
         }
         if let Token::Proc() = &p.token {
             let name = eat_token!(self);
             if let Token::Identifier(n) = name.token.clone() {
                 let _open = eat_token!(self);
-                let mut args: Vec<String> = vec![];
-                let do_loop = true;
+                let mut args: Vec<(String, Option<Type>)> = vec![];
                 let mut depth = 0;
-                while do_loop {
+                loop {
                     depth += 1;
                     if depth > 1000 {
-                        panic!("Reached maximum argument find depth of 1000! You have way too many arguments!");
+                        return Err(error_at(
+                            peek_token!(self).span,
+                            "Reached maximum argument find depth of 1000! You have way too many arguments!",
+                        ));
                     }
                     let peek = peek_token!(self);
                     if let Token::CloseParen() = peek.token {
@@ -643,14 +1160,13 @@ impl Parser<'_> {
                     }
                     let d = eat_token!(self);
                     if let Token::Identifier(ve) = d.token.clone() {
-                        args.push(ve[0].clone());
+                        let argtype = self.parse_annotation()?;
+                        args.push((ve[0].clone(), argtype));
                     } else {
-                        error_at(
-                            &d.filen,
-                            &d.linen,
-                            &d.charn,
+                        return Err(error_at(
+                            d.span.clone(),
                             &format!("Expected Identifier, got {:?}", d.token),
-                        );
+                        ));
                     };
 
                     let peek = peek_token!(self);
@@ -659,17 +1175,16 @@ impl Parser<'_> {
                         continue;
                     }
                 }
+                let rettype = self.parse_return_annotation()?;
 
                 // get body of program
                 {
                     let then = eat_token!(self);
-                    if !matches!(then.token, Token::Then(..)) {
-                        error_at(
-                            &then.filen,
-                            &then.linen,
-                            &then.charn,
+                    if !matches!(then.token, Token::Then(..) | Token::Colon(..)) {
+                        return Err(error_at(
+                            then.span.clone(),
                             &format!("Expected \"then\" keyword, got {:?}", then.token),
-                        )
+                        ));
                     };
                 }
                 let mut key = peek_token!(self);
@@ -680,24 +1195,22 @@ impl Parser<'_> {
                         break;
                     }
                     if let Token::EOF() = key.token {
-                        error_at(
-                            &p.filen,
-                            &p.linen,
-                            &p.charn,
-                            &format!("Prematurely reached EOF, did you end your proc?"),
-                        )
+                        return Err(error_at(
+                            p.span.clone(),
+                            "Prematurely reached EOF, did you end your proc?",
+                        ));
                     }
-                    program.push(self.parse_expression());
+                    program.push(self.parse_expression()?);
                     key = peek_token!(self);
                 }
-                return ctwl!(Expr::Proc(n, args, program), p);
+                let mut proc = ctwl!(self, Expr::Proc(n, args, rettype, program), p.span.clone());
+                proc.doc = p.doc.clone();
+                return Ok(proc);
             } else {
-                error_at(
-                    &name.filen,
-                    &name.linen,
-                    &name.charn,
+                return Err(error_at(
+                    name.span.clone(),
                     &format!("Expected Identifier, got {:?}", name.token),
-                )
+                ));
             }
         }
         if let Token::Identifier(parts) = &p.token {
@@ -709,58 +1222,134 @@ impl Parser<'_> {
                         _ = eat_token!(self);
                         break;
                     }
-                    let expr = self.parse_expression();
+                    let expr = self.parse_expression()?;
                     arguments.push(expr);
                     if let Token::Comma() = peek_token!(self).token {
                         _ = eat_token!(self);
                         continue;
                     }
                 }
-                return ctwl!(Expr::Call(parts.clone(), arguments), p);
+                return Ok(ctwl!(
+                    self,
+                    Expr::Call(parts.clone(), arguments),
+                    p.span.clone()
+                ));
             } else if let Token::OperatorSet() = peek_token!(self).token {
                 // setting variable
                 _ = eat_token!(self);
-                let expr = self.parse_expression();
-                return ctwl!(Expr::VariableSet(parts.clone(), Box::new(expr)), p);
+                let expr = self.parse_expression()?;
+                return Ok(ctwl!(
+                    self,
+                    Expr::VariableSet(parts.clone(), Box::new(expr)),
+                    p.span.clone()
+                ));
             } else {
-                return ctwl!(Expr::Identifier(parts.to_vec()), p);
+                return Ok(ctwl!(
+                    self,
+                    Expr::Identifier(parts.to_vec()),
+                    p.span.clone()
+                ));
             }
         }
         if let Token::OpenParen() = &p.token {
-            let expr = self.parse_expression();
+            let expr = self.parse_expression()?;
             if let Token::EOF() = peek_token!(self).token {
-                error_at(
-                    &p.filen,
-                    &p.linen,
-                    &p.charn,
+                return Err(error_at(
+                    p.span.clone(),
                     &format!("Prematurely reached EOF, did you end your grouping?"),
-                )
+                ));
             }
             let close = eat_token!(self).token.clone();
             if let Token::CloseParen() = close {
-                return ctwl!(Expr::Group(Box::new(expr)), p);
+                return Ok(ctwl!(self, Expr::Group(Box::new(expr)), p.span.clone()));
             }
-            error_at(
-                &p.filen,
-                &p.linen,
-                &p.charn,
+            return Err(error_at(
+                p.span.clone(),
                 &format!("Expected ')' after expression, instead got: {:?}", close),
-            )
+            ));
         }
         if let Token::EOF() = &p.token {
-            error_at(
-                &p.filen,
-                &p.linen,
-                &p.charn,
+            return Err(error_at(
+                p.span.clone(),
                 &format!("Prematurely reached EOF"),
-            )
+            ));
         }
 
-        error_at(
-            &p.filen,
-            &p.linen,
-            &p.charn,
+        Err(error_at(
+            p.span.clone(),
             &format!("Token not implemented or invalid token: {:?}", p),
-        )
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn parse(source: &str) -> ExprWL {
+        let lexer = Lexer::new("<test>", source);
+        let program = Parser::new(lexer.tokens()).parse_program().unwrap();
+        program
+    }
+
+    fn lines(source: &str) -> Vec<ExprWL> {
+        match parse(source).expr {
+            Expr::Program(lines) => lines,
+            expr => panic!("Expected a program, got {:?}", expr),
+        }
+    }
+
+    #[test]
+    fn programs_span_their_code() {
+        let program = parse("\n# a comment\nlet x = 1\nlet y = -x + 2\n\n");
+        assert_eq!(
+            program.span.text().as_deref(),
+            Some("let x = 1\nlet y = -x + 2")
+        );
+        assert_eq!(program.span.to_string(), "<test>:3:1");
+        assert_eq!(parse("").span.text().as_deref(), Some(""));
+    }
+
+    #[test]
+    fn expressions_span_their_tokens() {
+        let lines = lines("let y = -x + 2 * z\nnot done");
+        let Expr::VariableDeclaration(_, _, value) = &lines[0].expr else {
+            panic!("Expected a declaration, got {:?}", lines[0].expr);
+        };
+        assert_eq!(value.span.text().as_deref(), Some("-x + 2 * z"));
+        let Expr::Binary(_, left, right) = &value.expr else {
+            panic!("Expected a binary, got {:?}", value.expr);
+        };
+        assert_eq!(left.span.text().as_deref(), Some("-x"));
+        assert_eq!(right.span.text().as_deref(), Some("2 * z"));
+        assert_eq!(lines[1].span.text().as_deref(), Some("not done"));
+    }
+
+    #[test]
+    fn tests_are_named_on_their_own_line() {
+        let test = lines("test \"adds\" then\n    1 + 1\nend");
+        assert!(
+            matches!(&test[0].expr, Expr::Test(name, body) if name == "adds" && body.len() == 1)
+        );
+        // otherwise `test` is just a name
+        let code = lines("let test = 1\ntest\n\"a string\"");
+        assert_eq!(code.len(), 3);
+        assert!(matches!(&code[1].expr, Expr::Identifier(name) if name == &["test"]));
+        assert!(
+            matches!(&code[2].expr, Expr::Literal(LiteralType::String, text) if text == "a string")
+        );
+    }
+
+    #[test]
+    fn for_bounds_span_their_own_token() {
+        let lines = lines("for i = 0 to limit then\n    i\nend");
+        let Expr::For(_, start, end, _) = &lines[0].expr else {
+            panic!("Expected a for loop, got {:?}", lines[0].expr);
+        };
+        assert_eq!(start.span.text().as_deref(), Some("0"));
+        assert_eq!(start.span.to_string(), "<test>:1:9");
+        assert_eq!(end.span.text().as_deref(), Some("limit"));
+        assert_eq!(lines[0].span.to_string(), "<test>:1:1");
     }
 }
@@ -0,0 +1,226 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+
+use crate::dump::{self, DumpFormat};
+use crate::error::Error;
+use crate::interpreter::Interpreter;
+use crate::lexer::{Lexer, Token, TWL};
+use crate::limits::Limits;
+use crate::parser::{Expr, ExprWL, Parser};
+use crate::value::Value;
+
+const HELP: &str = "\
+Type statements to run them, and expressions to print their values.
+A line opening more blocks with `then` or `:` than it ends keeps reading until
+they are all ended.
+
+:help         show this message
+:reset        forget everything declared so far
+:load <file>  run the top level of a file, declaring its procs
+:ast <code>   print the AST of some code without running it
+:quit         exit, as does Ctrl-D";
+
+fn parse(name: &str, source: &str, limits: &Limits) -> Result<Vec<ExprWL>, Error> {
+    let lexer = Lexer::new(name, source);
+    let program = Parser::new(lexer.tokens())
+        .with_max_stack(limits.max_stack)
+        .parse_program()?;
+    match program.expr {
+        Expr::Program(code) => Ok(code),
+        _ => unreachable!("Parsing a program always gives a program"),
+    }
+}
+
+fn report<T>(result: Result<T, Error>) -> Option<T> {
+    result.map_err(|e| eprintln!("{:#}", e)).ok()
+}
+
+/// Whether the input so far has blocks, comments or strings that aren't
+/// closed yet
+fn incomplete(source: &str) -> bool {
+    match Lexer::new("<repl>", source).lex() {
+        Ok(tokens) => block_depth(&tokens) > 0,
+        Err(e) => {
+            e.reason == "Block comment not ended" || e.reason == "Triple-quoted string not ended"
+        }
+    }
+}
+
+/// How many blocks are opened by `tokens` and not ended. A `:` opens a
+/// block in place of `then` unless it starts a type annotation, which is
+/// in a proc's parentheses, after the name in a `let` or `const`, or after
+/// the parentheses with a return type and then `then` or `:` following it.
+fn block_depth(tokens: &[TWL]) -> i64 {
+    let mut depth = 0;
+    let mut parens = 0;
+    for (i, t) in tokens.iter().enumerate() {
+        match t.token {
+            Token::Then() => depth += 1,
+            Token::End() => depth -= 1,
+            Token::OpenParen() => parens += 1,
+            Token::CloseParen() => parens -= 1,
+            Token::Colon() if parens == 0 && !is_annotation(tokens, i) => depth += 1,
+            _ => {}
+        }
+    }
+    depth
+}
+
+/// Whether the `:` at `tokens[colon]` starts a type annotation outside of
+/// parentheses
+fn is_annotation(tokens: &[TWL], colon: usize) -> bool {
+    let before = |n: usize| colon.checked_sub(n).map(|i| &tokens[i].token);
+    match (before(2), before(1)) {
+        (Some(Token::Let() | Token::Const()), Some(Token::Identifier(_))) => true,
+        (_, Some(Token::CloseParen())) => {
+            Parser::new(tokens[colon + 1..].iter().cloned().map(Ok)).is_return_type_at(0)
+        }
+        _ => false,
+    }
+}
+
+/// Reads code from stdin a statement at a time, keeping everything that
+/// was declared between them
+pub struct Repl {
+    interpreter: Interpreter,
+    limits: Limits,
+}
+
+impl Repl {
+    pub fn new(limits: Limits) -> Self {
+        Self {
+            interpreter: Interpreter::new().with_limits(limits),
+            limits,
+        }
+    }
+
+    pub fn run(&mut self) {
+        println!(
+            "Bull Script {}, type :help for help",
+            env!("CARGO_PKG_VERSION")
+        );
+        let stdin = io::stdin();
+        let mut input = String::new();
+        loop {
+            print!("{}", if input.is_empty() { "> " } else { "... " });
+            _ = io::stdout().flush();
+            let mut line = String::new();
+            match stdin.lock().read_line(&mut line) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {}
+            }
+            if input.is_empty() && line.trim_start().starts_with(':') {
+                if !self.command(line.trim()) {
+                    break;
+                }
+                continue;
+            }
+            input.push_str(&line);
+            if !incomplete(&input) {
+                let source = std::mem::take(&mut input);
+                self.eval(&source);
+            }
+        }
+        println!();
+    }
+
+    /// Runs a `:command`, returning false if the REPL should exit
+    fn command(&mut self, line: &str) -> bool {
+        let (command, arg) = line.split_once(' ').unwrap_or((line, ""));
+        let arg = arg.trim();
+        match command {
+            ":help" => println!("{}", HELP),
+            ":reset" => {
+                self.interpreter = Interpreter::new().with_limits(self.limits);
+                println!("Forgot everything declared so far");
+            }
+            ":load" => match fs::read_to_string(arg) {
+                Ok(source) => {
+                    let source = source.replace('\r', "");
+                    if let Some(code) = report(parse(arg, &source, &self.limits)) {
+                        for ex in &code {
+                            if let Err(e) = self.interpreter.run_statement(ex) {
+                                eprintln!("{:#}", e);
+                                break;
+                            }
+                        }
+                    }
+                }
+                Err(e) => eprintln!("Could not read {}: {}", arg, e),
+            },
+            ":ast" => {
+                if let Some(code) = report(parse("<repl>", arg, &self.limits)) {
+                    for ex in &code {
+                        print!("{}", dump::ast(ex, DumpFormat::Text));
+                    }
+                }
+            }
+            ":quit" | ":q" => return false,
+            _ => eprintln!("Unknown command {}, type :help for help", command),
+        }
+        true
+    }
+
+    fn eval(&mut self, source: &str) {
+        let Some(code) = report(parse("<repl>", source, &self.limits)) else {
+            return;
+        };
+        for ex in &code {
+            match self.interpreter.run_statement(ex) {
+                Ok(Some(Value::Null) | None) => {}
+                Ok(Some(value)) => println!("{}", value),
+                Err(e) => {
+                    eprintln!("{:#}", e);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+impl Default for Repl {
+    fn default() -> Self {
+        Self::new(Limits::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocks_opened_with_then() {
+        assert!(incomplete("if true then\n"));
+        assert!(incomplete("proc f() then\n    if true then\n    end\n"));
+        assert!(!incomplete("proc f() then\n    return 1\nend\n"));
+    }
+
+    #[test]
+    fn blocks_opened_with_a_colon() {
+        assert!(incomplete("if true:\n"));
+        assert!(incomplete("for i = 0 to 3:\n"));
+        assert!(incomplete("proc f():\n    int(2)\n"));
+        assert!(incomplete("namespace a:\n    proc b():\n    end\n"));
+        assert!(!incomplete("while false:\nend\n"));
+    }
+
+    #[test]
+    fn annotations_do_not_open_blocks() {
+        assert!(!incomplete("let x: int = 1\n"));
+        assert!(!incomplete("const name: str = \"bull\"\n"));
+        assert!(!incomplete(
+            "proc f(a: int, b: list[int]): int then\n    return a\nend\n"
+        ));
+        assert!(!incomplete(
+            "proc g(): proc(int): int:\n    return f\nend\n"
+        ));
+        assert!(incomplete("proc f(a: int): int:\n"));
+    }
+
+    #[test]
+    fn unended_comments_and_strings() {
+        assert!(incomplete("#[ a comment\n"));
+        assert!(incomplete("let s = \"\"\"\n    text\n"));
+        assert!(!incomplete("let s = 1 # a comment\n"));
+    }
+}
@@ -0,0 +1,338 @@
+use std::fmt::{Debug, Display};
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+#[cfg(feature = "serde")]
+use std::sync::{Mutex, Weak};
+
+/// A file the lexer read. Its tokens and spans share it, so it is freed
+/// along with the last of them, and they can be shown on any thread.
+#[derive(Clone)]
+pub struct SourceFile(Arc<FileData>);
+
+struct FileData {
+    name: String,
+    /// `None` when only the name is known, such as for a span read back
+    /// from somewhere else
+    text: Option<Arc<str>>,
+    /// The byte offset each line starts at
+    line_starts: Vec<usize>,
+    /// A hash of the text, so deserialized spans only find the file if its
+    /// text is the one they were made from
+    #[cfg(feature = "serde")]
+    hash: Option<u64>,
+}
+
+impl SourceFile {
+    pub fn new(name: &str, text: &str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        SourceFile::register(FileData {
+            name: name.to_string(),
+            text: Some(Arc::from(text)),
+            line_starts,
+            #[cfg(feature = "serde")]
+            hash: Some(hash(text)),
+        })
+    }
+
+    /// A file whose text isn't known, so its spans can only be shown by name
+    pub fn named(name: &str) -> Self {
+        SourceFile::register(FileData {
+            name: name.to_string(),
+            text: None,
+            line_starts: vec![],
+            #[cfg(feature = "serde")]
+            hash: None,
+        })
+    }
+
+    #[cfg(not(feature = "serde"))]
+    fn register(data: FileData) -> Self {
+        SourceFile(Arc::new(data))
+    }
+
+    /// Keeps track of the file while it is alive, so deserialized spans
+    /// can find it again by name
+    #[cfg(feature = "serde")]
+    fn register(data: FileData) -> Self {
+        let file = Arc::new(data);
+        let mut files = LIVE_FILES.lock().unwrap_or_else(|e| e.into_inner());
+        files.retain(|f| f.strong_count() > 0);
+        files.push(Arc::downgrade(&file));
+        SourceFile(file)
+    }
+
+    /// The file a deserialized span points into. That is the last file read
+    /// with the name and the text the span was made from that is still
+    /// alive, so its text is known as long as something lexed it again, or
+    /// else a file known only by its name that the other spans of the file
+    /// will share.
+    #[cfg(feature = "serde")]
+    fn find(name: &str, hash: Option<u64>) -> Self {
+        let candidates: Vec<Arc<FileData>> = {
+            let files = LIVE_FILES.lock().unwrap_or_else(|e| e.into_inner());
+            files
+                .iter()
+                .rev()
+                .filter_map(Weak::upgrade)
+                .filter(|f| f.name == name)
+                .collect()
+        };
+        let found = candidates
+            .iter()
+            .find(|f| hash.is_some() && f.hash == hash)
+            .or_else(|| candidates.iter().find(|f| f.text.is_none()));
+        match found {
+            Some(file) => SourceFile(file.clone()),
+            None => SourceFile::named(name),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    /// The text of the file, which is empty if it isn't known
+    pub fn text(&self) -> Arc<str> {
+        self.0.text.clone().unwrap_or_else(|| Arc::from(""))
+    }
+
+    /// The 0-based line holding the byte at `offset`, if it is in the file
+    fn line_of(&self, offset: usize) -> Option<usize> {
+        let text = self.0.text.as_ref()?;
+        if offset > text.len() || !text.is_char_boundary(offset) {
+            return None;
+        }
+        Some(match self.0.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next) => next - 1,
+        })
+    }
+}
+
+/// Files are the same only if they were read once, as two reads of a file
+/// can differ
+impl PartialEq for SourceFile {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for SourceFile {}
+
+impl Hash for SourceFile {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Arc::as_ptr(&self.0).hash(state);
+    }
+}
+
+impl Debug for SourceFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SourceFile({:?})", self.0.name)
+    }
+}
+
+/// A range of bytes in a source file
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "SpanData", into = "SpanData")
+)]
+pub struct Span {
+    pub file: SourceFile,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(file: SourceFile, start: usize, end: usize) -> Self {
+        Span { file, start, end }
+    }
+
+    /// The span covering both this span and `other`
+    pub fn to(&self, other: &Span) -> Span {
+        Span {
+            file: self.file.clone(),
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+
+    /// The 1-based line and column the span starts at, counting columns in
+    /// characters, or `None` if the file's text isn't known
+    pub fn line_col(&self) -> Option<(usize, usize)> {
+        let line = self.file.line_of(self.start)?;
+        let line_start = self.file.0.line_starts[line];
+        let column = self.file.text()[line_start..self.start].chars().count();
+        Some((line + 1, column + 1))
+    }
+
+    /// The text of the first line the span is on
+    pub fn line_text(&self) -> Option<String> {
+        let line = self.file.line_of(self.start)?;
+        let text = self.file.text();
+        let line_start = self.file.0.line_starts[line];
+        let line_end = text[line_start..]
+            .find('\n')
+            .map_or(text.len(), |end| line_start + end);
+        Some(text[line_start..line_end].to_string())
+    }
+
+    /// The source text the span covers
+    pub fn text(&self) -> Option<String> {
+        self.file.line_of(self.start)?;
+        self.file.line_of(self.end)?;
+        Some(self.file.text()[self.start..self.end].to_string())
+    }
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line_col() {
+            Some((line, column)) => write!(f, "{}:{}:{}", self.file.name(), line, column),
+            None => write!(
+                f,
+                "{} (bytes {}..{})",
+                self.file.name(),
+                self.start,
+                self.end
+            ),
+        }
+    }
+}
+
+/// Every file still in use, as serializing a span only keeps its file's name
+#[cfg(feature = "serde")]
+static LIVE_FILES: Mutex<Vec<Weak<FileData>>> = Mutex::new(vec![]);
+
+/// FNV-1a, which unlike the hashers of the standard library gives the
+/// same hash in every build, so spans can be read back by another one
+#[cfg(feature = "serde")]
+fn hash(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// How a span is serialized, with its file by name and the hash of its
+/// text, as the file itself only lives as long as the process that read it
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SpanData {
+    file: String,
+    #[serde(default)]
+    hash: Option<u64>,
+    start: usize,
+    end: usize,
+}
+
+#[cfg(feature = "serde")]
+impl From<Span> for SpanData {
+    fn from(span: Span) -> Self {
+        SpanData {
+            file: span.file.name().to_string(),
+            hash: span.file.0.hash,
+            start: span.start,
+            end: span.end,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<SpanData> for Span {
+    fn from(data: SpanData) -> Self {
+        Span::new(
+            SourceFile::find(&data.file, data.hash),
+            data.start,
+            data.end,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions() {
+        let file = SourceFile::new("test.bs", "let x = 1\n  let y = 2\n");
+        let span = Span::new(file, 12, 15);
+        assert_eq!(span.line_col(), Some((2, 3)));
+        assert_eq!(span.line_text().as_deref(), Some("  let y = 2"));
+        assert_eq!(span.text().as_deref(), Some("let"));
+        assert_eq!(span.to_string(), "test.bs:2:3");
+    }
+
+    #[test]
+    fn spans_outside_the_text() {
+        let span = Span::new(SourceFile::new("test.bs", "é"), 1, 40);
+        assert_eq!(span.line_col(), None);
+        assert_eq!(span.text(), None);
+        let span = Span::new(SourceFile::named("other.bs"), 3, 5);
+        assert_eq!(span.line_text(), None);
+        assert_eq!(span.to_string(), "other.bs (bytes 3..5)");
+    }
+
+    #[test]
+    fn spans_can_be_shown_on_other_threads() {
+        let span = Span::new(SourceFile::new("test.bs", "a\nb"), 2, 3);
+        let shown = std::thread::spawn(move || span.to_string()).join().unwrap();
+        assert_eq!(shown, "test.bs:2:1");
+    }
+
+    #[cfg(feature = "serde")]
+    fn parse(name: &str, text: &str) -> String {
+        use crate::lexer::Lexer;
+        use crate::parser::Parser;
+        let lexer = Lexer::new(name, text);
+        let program = Parser::new(lexer.tokens()).parse_program().unwrap();
+        serde_json::to_string(&program).unwrap()
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn deserialized_spans_find_their_file() {
+        use crate::parser::{Expr, ExprWL};
+        let text = "let x = 1\nlet y = x\n";
+        let json = parse("serde_live.bs", text);
+        let lexer = crate::lexer::Lexer::new("serde_live.bs", text);
+        let program: ExprWL = serde_json::from_str(&json).unwrap();
+        let Expr::Program(lines) = &program.expr else {
+            panic!("Expected a program, got {:?}", program.expr);
+        };
+        assert_eq!(lines[1].span.file, lexer.file);
+        assert_eq!(lines[1].span.to_string(), "serde_live.bs:2:1");
+        assert_eq!(lines[1].span.text().as_deref(), Some("let y = x"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn deserialized_spans_only_find_the_text_they_were_made_from() {
+        use crate::parser::{Expr, ExprWL};
+        let json = parse("serde_changed.bs", "let x = 1\nlet y = x\n");
+        let changed = crate::lexer::Lexer::new("serde_changed.bs", "let y = 2\n");
+        let program: ExprWL = serde_json::from_str(&json).unwrap();
+        let Expr::Program(lines) = &program.expr else {
+            panic!("Expected a program, got {:?}", program.expr);
+        };
+        assert_ne!(lines[1].span.file, changed.file);
+        assert_eq!(lines[0].span.file, lines[1].span.file);
+        assert_eq!(lines[1].span.text(), None);
+        assert_eq!(lines[1].span.to_string(), "serde_changed.bs (bytes 10..19)");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn deserialized_spans_without_their_file() {
+        use crate::parser::{Expr, ExprWL};
+        let json = parse("serde_gone.bs", "let x = 1\nlet y = x\n");
+        let program: ExprWL = serde_json::from_str(&json).unwrap();
+        let Expr::Program(lines) = &program.expr else {
+            panic!("Expected a program, got {:?}", program.expr);
+        };
+        assert_eq!(lines[0].span.file, lines[1].span.file);
+        assert_eq!(lines[1].span.to_string(), "serde_gone.bs (bytes 10..19)");
+    }
+}
@@ -0,0 +1,65 @@
+use crate::error::Error;
+use crate::interpreter::Interpreter;
+use crate::io::BufferIo;
+use crate::limits::Limits;
+use crate::parser::{Expr, ExprWL};
+use crate::source::Span;
+
+/// A `test "name" then ... end` block in a program
+pub struct TestCase<'a> {
+    pub name: String,
+    /// The namespace the test is in, which its names are resolved from
+    pub namespace: Vec<String>,
+    pub span: Span,
+    pub body: &'a [ExprWL],
+}
+
+/// Finds the tests of a program, including those in namespaces, in the
+/// order they are written
+pub fn find_tests(prog: &ExprWL) -> Vec<TestCase<'_>> {
+    let mut tests = vec![];
+    if let Expr::Program(code) = &prog.expr {
+        find_in(code, &mut vec![], &mut tests);
+    }
+    tests
+}
+
+fn find_in<'a>(code: &'a [ExprWL], namespace: &mut Vec<String>, tests: &mut Vec<TestCase<'a>>) {
+    for ex in code {
+        match &ex.expr {
+            Expr::Test(name, body) => tests.push(TestCase {
+                name: name.clone(),
+                namespace: namespace.clone(),
+                span: ex.span.clone(),
+                body,
+            }),
+            Expr::Namespace(name, body) => {
+                let depth = namespace.len();
+                namespace.extend(name.iter().cloned());
+                find_in(body, namespace, tests);
+                namespace.truncate(depth);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// How a test went, with what it printed
+pub struct TestResult {
+    pub output: String,
+    pub error: Option<Error>,
+}
+
+/// Runs a test in an interpreter of its own, so tests can't see what other
+/// tests changed, and keeps what it prints rather than printing it
+pub fn run_test(prog: &ExprWL, test: &TestCase, limits: Limits) -> TestResult {
+    let io = BufferIo::new();
+    let mut interpreter = Interpreter::new().with_io(io.clone()).with_limits(limits);
+    let result = interpreter.run_test(prog, test);
+    let mut output = io.out();
+    output.push_str(&io.err());
+    TestResult {
+        output,
+        error: result.err(),
+    }
+}
@@ -0,0 +1,207 @@
+use std::{collections::BTreeMap, fmt::Display, rc::Rc};
+
+use crate::bigint::BigInt;
+use crate::native::NativeProc;
+use crate::parser::{Alignment, ExprWL, FormatSpec, Type};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Procedure {
+    pub name: Vec<String>,
+    pub params: Vec<(String, Option<Type>)>,
+    pub ret: Option<Type>,
+    pub body: Vec<ExprWL>,
+    /// The namespace the proc was declared in, used to resolve
+    /// names relative to it
+    pub namespace: Vec<String>,
+    pub doc: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Boolean(bool),
+    Integer(i64),
+    Float(f64),
+    BigInt(BigInt),
+    String(String),
+    List(Vec<Value>),
+    Map(BTreeMap<String, Value>),
+    Proc(Rc<Procedure>),
+    Builtin(String),
+    Native(Rc<NativeProc>),
+}
+
+impl Value {
+    pub fn type_name(&self) -> String {
+        match self {
+            Value::Null => "null".to_string(),
+            Value::Boolean(_) => "bool".to_string(),
+            Value::Integer(_) => "int".to_string(),
+            Value::Float(_) => "float".to_string(),
+            Value::BigInt(_) => "bigint".to_string(),
+            Value::String(_) => "str".to_string(),
+            Value::List(_) => "list".to_string(),
+            Value::Map(_) => "map".to_string(),
+            Value::Proc(p) => Type::Proc(Some((
+                p.params
+                    .iter()
+                    .map(|(_, t)| t.clone().unwrap_or(Type::Any))
+                    .collect(),
+                Box::new(p.ret.clone().unwrap_or(Type::Any)),
+            )))
+            .to_string(),
+            Value::Native(p) => {
+                Type::Proc(Some((p.params.clone(), Box::new(Type::Any)))).to_string()
+            }
+            Value::Builtin(_) => "proc".to_string(),
+        }
+    }
+
+    /// Checks whether this value may be stored somewhere annotated with `ty`
+    pub fn is_of_type(&self, ty: &Type) -> bool {
+        match (ty, self) {
+            (Type::Any, _) => true,
+            (Type::Int, Value::Integer(_) | Value::BigInt(_)) => true,
+            (Type::BigInt, Value::Integer(_) | Value::BigInt(_)) => true,
+            // ints are promoted to floats where a float is expected
+            (Type::Float, Value::Integer(_) | Value::BigInt(_) | Value::Float(_)) => true,
+            (Type::Str, Value::String(_)) => true,
+            (Type::Bool, Value::Boolean(_)) => true,
+            (Type::List(None), Value::List(_)) => true,
+            (Type::List(Some(t)), Value::List(items)) => items.iter().all(|v| v.is_of_type(t)),
+            (Type::Map(None), Value::Map(_)) => true,
+            (Type::Map(Some(t)), Value::Map(items)) => items.values().all(|v| v.is_of_type(t)),
+            (Type::Proc(None), Value::Proc(_) | Value::Builtin(_) | Value::Native(_)) => true,
+            (Type::Proc(Some(_)), Value::Builtin(_)) => true,
+            (Type::Proc(Some((params, _))), Value::Native(p)) => p.params.len() == params.len(),
+            (Type::Proc(Some((params, ret))), Value::Proc(p)) => {
+                let compatible = |declared: &Option<Type>, expected: &Type| match declared {
+                    None | Some(Type::Any) => true,
+                    Some(t) => *expected == Type::Any || t == expected,
+                };
+                p.params.len() == params.len()
+                    && p.params
+                        .iter()
+                        .zip(params)
+                        .all(|((_, declared), expected)| compatible(declared, expected))
+                    && compatible(&p.ret, ret)
+            }
+            _ => false,
+        }
+    }
+
+    /// Converts ints stored somewhere annotated as `float` or `bigint`
+    pub fn promote(self, ty: &Type) -> Value {
+        match (ty, self) {
+            (Type::Float, Value::Integer(i)) => Value::Float(i as f64),
+            (Type::Float, Value::BigInt(b)) => Value::Float(b.to_f64()),
+            (Type::BigInt, Value::Integer(i)) => Value::BigInt(BigInt::from(i)),
+            (_, v) => v,
+        }
+    }
+
+    /// The value as a float, if it is numeric
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            Value::Integer(i) => Some(*i as f64),
+            Value::Float(f) => Some(*f),
+            Value::BigInt(b) => Some(b.to_f64()),
+            _ => None,
+        }
+    }
+
+    /// The value as a big integer, if it is an int or bigint
+    pub fn as_bigint(&self) -> Option<BigInt> {
+        match self {
+            Value::Integer(i) => Some(BigInt::from(*i)),
+            Value::BigInt(b) => Some(b.clone()),
+            _ => None,
+        }
+    }
+
+    /// Formats the value for an interpolated string
+    pub fn format(&self, spec: &FormatSpec) -> String {
+        let numeric = self.as_float().is_some();
+        let text = match (spec.precision, self) {
+            (Some(precision), Value::Float(f)) => format!("{:.*}", precision, f),
+            (Some(precision), Value::Integer(_) | Value::BigInt(_)) => {
+                format!("{:.*}", precision, self.as_float().unwrap_or(f64::NAN))
+            }
+            (Some(precision), _) => self.to_string().chars().take(precision).collect(),
+            (None, _) => self.to_string(),
+        };
+        let len = text.chars().count();
+        if len >= spec.width {
+            return text;
+        }
+        let pad = spec.width - len;
+        if spec.zero && numeric && spec.align.is_none() {
+            let sign = if text.starts_with('-') { 1 } else { 0 };
+            return format!("{}{}{}", &text[..sign], "0".repeat(pad), &text[sign..]);
+        }
+        let fill = spec.fill.to_string();
+        let default = if numeric {
+            Alignment::Right
+        } else {
+            Alignment::Left
+        };
+        match spec.align.as_ref().unwrap_or(&default) {
+            Alignment::Left => text + &fill.repeat(pad),
+            Alignment::Right => fill.repeat(pad) + &text,
+            Alignment::Center => fill.repeat(pad / 2) + &text + &fill.repeat(pad - pad / 2),
+        }
+    }
+
+    /// Roughly how many bytes the value takes up on the heap, which is
+    /// what `Limits::with_max_heap` counts
+    pub fn heap_size(&self) -> usize {
+        let item = std::mem::size_of::<Value>();
+        match self {
+            Value::BigInt(b) => b.heap_size(),
+            Value::String(s) => s.len(),
+            Value::List(l) => l.iter().map(|v| item + v.heap_size()).sum(),
+            Value::Map(m) => m.iter().map(|(k, v)| k.len() + item + v.heap_size()).sum(),
+            _ => 0,
+        }
+    }
+
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Null => false,
+            Value::Boolean(b) => *b,
+            Value::Integer(i) => *i != 0,
+            Value::Float(f) => *f != 0.0,
+            Value::BigInt(b) => !b.is_zero(),
+            Value::String(s) => !s.is_empty(),
+            Value::List(l) => !l.is_empty(),
+            Value::Map(m) => !m.is_empty(),
+            Value::Proc(_) | Value::Builtin(_) | Value::Native(_) => true,
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Null => write!(f, "null"),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::Integer(i) => write!(f, "{}", i),
+            // keep the decimal point so floats can be told apart from ints
+            Value::Float(n) if n.fract() == 0.0 && n.abs() < 1e16 => write!(f, "{}.0", n),
+            Value::Float(n) => write!(f, "{}", n),
+            Value::BigInt(b) => write!(f, "{}", b),
+            Value::String(s) => write!(f, "{}", s),
+            Value::List(items) => {
+                let items: Vec<String> = items.iter().map(|v| v.to_string()).collect();
+                write!(f, "[{}]", items.join(", "))
+            }
+            Value::Map(items) => {
+                let items: Vec<String> = items.iter().map(|(k, v)| format!("{k}: {v}")).collect();
+                write!(f, "{{{}}}", items.join(", "))
+            }
+            Value::Proc(p) => write!(f, "<proc {}>", p.name.join(".")),
+            Value::Builtin(name) => write!(f, "<builtin {}>", name),
+            Value::Native(p) => write!(f, "<native {}>", p.name.join(".")),
+        }
+    }
+}
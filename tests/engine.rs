@@ -0,0 +1,132 @@
+use bull_script::{BufferIo, Engine, ErrorKind, Value};
+
+#[test]
+fn eval_gives_the_last_value() {
+    let mut engine = Engine::new();
+    assert_eq!(engine.eval("1 + 2").unwrap(), Value::Integer(3));
+    assert_eq!(engine.eval("let x = 1").unwrap(), Value::Null);
+    // declarations stay around for the next eval
+    assert_eq!(engine.eval("x + 1").unwrap(), Value::Integer(2));
+}
+
+#[test]
+fn call() {
+    let mut engine = Engine::new();
+    engine
+        .eval("namespace ns then proc double(x) then return x * 2 end end")
+        .unwrap();
+    for n in 0..3 {
+        let doubled = engine.call("ns.double", vec![Value::Integer(n)]).unwrap();
+        assert_eq!(doubled, Value::Integer(n * 2));
+    }
+}
+
+#[test]
+fn globals() {
+    let mut engine = Engine::new();
+    assert_eq!(engine.get_global("config.name"), None);
+    engine.set_global("config.name", Value::String("bull".to_string()));
+    assert_eq!(
+        engine.eval("config.name + \"!\"").unwrap(),
+        Value::String("bull!".to_string())
+    );
+    engine.eval("let count = 1\ncount = count + 1").unwrap();
+    assert_eq!(engine.get_global("count"), Some(Value::Integer(2)));
+}
+
+#[test]
+fn parse_errors() {
+    let mut engine = Engine::new();
+    let error = engine.eval("let x = 1\nlet = 2").unwrap_err();
+    assert_eq!(error.kind, ErrorKind::Script);
+    assert_eq!(error.span.to_string(), "<eval>:2:5");
+    let error = engine.eval("let s = \"not ended").unwrap_err();
+    assert_eq!(error.reason, "String not ended");
+}
+
+#[test]
+fn runtime_errors() {
+    let mut engine = Engine::new();
+    let error = engine.eval("let x = 1\nlet y = x / 0").unwrap_err();
+    assert_eq!(error.kind, ErrorKind::Script);
+    assert_eq!(error.span.line_col().map(|(line, _)| line), Some(2));
+    // a failed eval leaves the engine usable
+    assert_eq!(engine.eval("x").unwrap(), Value::Integer(1));
+}
+
+#[test]
+fn calling_an_unknown_proc() {
+    let mut engine = Engine::new();
+    let error = engine.call("ns.missing", vec![]).unwrap_err();
+    assert_eq!(error.kind, ErrorKind::Script);
+    assert_eq!(error.span.to_string(), "<call>:1:1");
+    assert_eq!(error.span.text().as_deref(), Some("ns.missing"));
+    // calling the same name again points into the same file
+    let again = engine.call("ns.missing", vec![]).unwrap_err();
+    assert_eq!(again.span.file, error.span.file);
+}
+
+#[test]
+fn calling_with_the_wrong_arguments() {
+    let mut engine = Engine::new();
+    engine
+        .eval("proc add(a: int, b: int) then return a + b end")
+        .unwrap();
+    assert!(engine.call("add", vec![Value::Integer(1)]).is_err());
+    let error = engine
+        .call("add", vec![Value::Integer(1), Value::Boolean(true)])
+        .unwrap_err();
+    assert_eq!(error.kind, ErrorKind::Script);
+}
+
+#[test]
+fn running_a_missing_file() {
+    let mut engine = Engine::new();
+    let error = engine.run_file("tests/does-not-exist.bs").unwrap_err();
+    assert_eq!(error.kind, ErrorKind::Script);
+    assert!(error
+        .reason
+        .starts_with("Could not read tests/does-not-exist.bs"));
+}
+
+#[test]
+fn running_a_file() {
+    let path = std::env::temp_dir().join("bull-script-engine-run-file.bs");
+    std::fs::write(
+        &path,
+        "import std\nlet greeting = \"Hello\"\nproc main() then\n    std.printval(greeting)\nend\n",
+    )
+    .unwrap();
+    let io = BufferIo::new();
+    let mut engine = Engine::new().with_io(io.clone());
+    engine.run_file(&path).unwrap();
+    assert_eq!(io.out(), "Hello");
+    assert_eq!(
+        engine.get_global("greeting"),
+        Some(Value::String("Hello".to_string()))
+    );
+}
+
+#[test]
+fn running_a_file_only_calls_its_own_main() {
+    let path = std::env::temp_dir().join("bull-script-engine-no-main.bs");
+    std::fs::write(&path, "import std\nstd.printval(\"file \")\n").unwrap();
+    let io = BufferIo::new();
+    let mut engine = Engine::new().with_io(io.clone());
+    engine
+        .eval("import std\nproc main() then\n    std.printval(\"main\")\nend")
+        .unwrap();
+    engine.run_file(&path).unwrap();
+    assert_eq!(io.out(), "file ");
+}
+
+#[test]
+fn errors_are_only_coloured_when_asked() {
+    let mut engine = Engine::new();
+    let error = engine.eval("let x = 1 / 0").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "[ERROR]: At <eval>:1:9:\n\tDivision by zero\n  |\n1 | let x = 1 / 0\n  |         ^^^^^"
+    );
+    assert!(format!("{:#}", error).starts_with("\x1b[31;1m[ERROR]\x1b[0m: At <eval>:1:9:"));
+}
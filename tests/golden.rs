@@ -0,0 +1,199 @@
+//! Runs every script in `tests/` and `examples/` and compares what it prints with the
+//! `.out` and `.err` files next to it, a missing file meaning nothing should
+//! be printed. Run with `cargo test --test golden`, adding `-- --bless` to
+//! rewrite the expected output from what the scripts print now, or
+//! `-- <name>` to only run the scripts with `name` in their path.
+//!
+//! A script that should fail says so with `# expect-error: <reason>` at the
+//! end of the line the error is raised on. The error must be raised on that
+//! line with that reason in it, and the script must exit with 1 rather than
+//! 0. Blessing never changes these, so a script can't be blessed into
+//! failing differently.
+//!
+//! A script with a `# also-check` line is type checked as well, and `check`
+//! must report the same errors on the same lines as running it does.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+
+const SCRIPT_DIRS: [&str; 2] = ["tests", "examples"];
+const ANNOTATION: &str = "# expect-error:";
+const ALSO_CHECK: &str = "# also-check";
+
+/// An error a script should raise, with the line it should be raised on
+struct ExpectedError {
+    line: usize,
+    reason: String,
+}
+
+fn expected_errors(source: &str) -> Vec<ExpectedError> {
+    source
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let (_, reason) = line.split_once(ANNOTATION)?;
+            Some(ExpectedError {
+                line: i + 1,
+                reason: reason.trim().to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Removes the colours errors are printed with, so expectations are plain text
+fn strip_colours(text: &str) -> String {
+    let mut out = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            chars.by_ref().find(|&c| c == 'm');
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// The line and reason of each error in the output of a failed script
+fn raised_errors(stderr: &str) -> Vec<(usize, String)> {
+    let lines: Vec<&str> = stderr.lines().collect();
+    lines
+        .iter()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let location = line.strip_prefix("[ERROR]: At ")?.strip_suffix(':')?;
+            let mut parts = location.rsplitn(3, ':');
+            let _column = parts.next()?;
+            let line = parts.next()?.parse().ok()?;
+            let reason = lines.get(i + 1)?.trim().to_string();
+            Some((line, reason))
+        })
+        .collect()
+}
+
+/// Compares `actual` with the expectation file at `path`, or with `--bless`
+/// writes it there instead, removing the file if there is nothing to expect
+fn compare(path: &Path, actual: &str, bless: bool, problems: &mut Vec<String>) {
+    let expected = fs::read_to_string(path).unwrap_or_default();
+    if expected == actual {
+        return;
+    }
+    if bless {
+        let result = if actual.is_empty() {
+            fs::remove_file(path)
+        } else {
+            fs::write(path, actual)
+        };
+        if let Err(e) = result {
+            problems.push(format!("could not bless {}: {}", path.display(), e));
+        }
+        return;
+    }
+    problems.push(format!(
+        "{} does not match\n--- expected\n{}\n--- actual\n{}",
+        path.display(),
+        expected,
+        actual
+    ));
+}
+
+/// Runs a command of the interpreter on a script, giving its exit code,
+/// stdout and stderr without colours
+fn bull_script(command: &str, script: &Path) -> (Option<i32>, String, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_bull-script"))
+        .arg(command)
+        .arg(script)
+        .output()
+        .expect("the interpreter should start");
+    (
+        output.status.code(),
+        String::from_utf8_lossy(&output.stdout).to_string(),
+        strip_colours(&String::from_utf8_lossy(&output.stderr)),
+    )
+}
+
+/// Checks that `command` exited as expected and raised every expected error
+fn check_errors(
+    command: &str,
+    code: Option<i32>,
+    stderr: &str,
+    expected: &[ExpectedError],
+    problems: &mut Vec<String>,
+) {
+    let expected_code = if expected.is_empty() { 0 } else { 1 };
+    if code != Some(expected_code) {
+        problems.push(format!(
+            "{} exited with {:?} instead of {}",
+            command, code, expected_code
+        ));
+    }
+    let raised = raised_errors(stderr);
+    for error in expected {
+        let found = raised
+            .iter()
+            .any(|(line, reason)| *line == error.line && reason.contains(&error.reason));
+        if !found {
+            problems.push(format!(
+                "expected {} to raise an error on line {} with \"{}\"",
+                command, error.line, error.reason
+            ));
+        }
+    }
+}
+
+/// Runs a script, giving what went wrong with it if anything did
+fn run(script: &Path, bless: bool) -> Vec<String> {
+    let source = fs::read_to_string(script).unwrap_or_default();
+    let expected = expected_errors(&source);
+    let mut problems = vec![];
+
+    let (code, stdout, stderr) = bull_script("run", script);
+    check_errors("run", code, &stderr, &expected, &mut problems);
+    if source.lines().any(|line| line.trim() == ALSO_CHECK) {
+        let (code, _, check_stderr) = bull_script("check", script);
+        check_errors("check", code, &check_stderr, &expected, &mut problems);
+    }
+
+    compare(&script.with_extension("out"), &stdout, bless, &mut problems);
+    compare(&script.with_extension("err"), &stderr, bless, &mut problems);
+    problems
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let bless = args.iter().any(|a| a == "--bless");
+    // cargo passes its own flags through, like `--nocapture`, which aren't
+    // filters
+    let filters: Vec<&String> = args.iter().filter(|a| !a.starts_with("--")).collect();
+
+    let mut scripts: Vec<PathBuf> = SCRIPT_DIRS
+        .iter()
+        .flat_map(|dir| fs::read_dir(dir).expect("the script folders should exist"))
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "bs"))
+        .filter(|path| {
+            let name = path.display().to_string();
+            filters.is_empty() || filters.iter().any(|f| name.contains(f.as_str()))
+        })
+        .collect();
+    scripts.sort();
+
+    let mut failed = 0;
+    for script in &scripts {
+        let problems = run(script, bless);
+        if problems.is_empty() {
+            println!("{} ... ok", script.display());
+        } else {
+            println!("{} ... FAILED", script.display());
+            for problem in problems {
+                println!("    {}", problem.replace('\n', "\n    "));
+            }
+            failed += 1;
+        }
+    }
+    println!("{} passed, {} failed", scripts.len() - failed, failed);
+    if failed > 0 {
+        process::exit(1);
+    }
+}
@@ -0,0 +1,125 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use bull_script::{Engine, ErrorKind, Limits, Value};
+
+fn kind(engine: &mut Engine, code: &str) -> ErrorKind {
+    engine.eval(code).unwrap_err().kind
+}
+
+#[test]
+fn step_limit() {
+    let mut engine = Engine::new().with_limits(Limits::new().with_max_steps(10_000));
+    assert_eq!(
+        kind(&mut engine, "while true then end"),
+        ErrorKind::StepLimit
+    );
+    // each run gets all of its steps again
+    engine
+        .eval("let n = 0\nfor i = 0 to 100 then n = n + i end")
+        .unwrap();
+}
+
+#[test]
+fn timeout() {
+    let limits = Limits::new().with_timeout(Duration::from_millis(100));
+    let mut engine = Engine::new().with_limits(limits);
+    let started = Instant::now();
+    assert_eq!(kind(&mut engine, "while true then end"), ErrorKind::Timeout);
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn call_depth() {
+    let mut engine = Engine::new().with_limits(Limits::new().with_max_call_depth(50));
+    engine
+        .eval("proc down(n) then if n is 0 then return 0 end return down(n - 1) end")
+        .unwrap();
+    engine.eval("down(40)").unwrap();
+    assert_eq!(kind(&mut engine, "down(60)"), ErrorKind::CallDepth);
+}
+
+#[test]
+fn heap_limit() {
+    let mut engine = Engine::new().with_limits(Limits::new().with_max_heap(1_000_000));
+    let code = "let s = \"x\"\nwhile true then s = s + s end";
+    assert_eq!(kind(&mut engine, code), ErrorKind::HeapLimit);
+}
+
+#[test]
+fn scripts_cannot_catch_limits() {
+    let mut engine = Engine::new().with_limits(Limits::new().with_max_steps(10_000));
+    let code = "import std\nproc spin() then while true then end end\nstd.assert_throws(spin)";
+    assert_eq!(kind(&mut engine, code), ErrorKind::StepLimit);
+}
+
+#[test]
+fn endless_recursion_on_a_default_thread() {
+    // a spawned thread only has 2 MiB of stack, which the default limits
+    // must leave enough of
+    let kind = kind_on_a_default_thread("proc f(n) then return f(n + 1) end f(0)".to_string());
+    assert_eq!(kind, ErrorKind::CallDepth);
+}
+
+/// The kind of error `code` raises with the default limits on a thread
+/// with the 2 MiB of stack spawned threads get
+fn kind_on_a_default_thread(code: String) -> ErrorKind {
+    thread::spawn(move || kind(&mut Engine::new(), &code))
+        .join()
+        .unwrap()
+}
+
+#[test]
+fn deeply_nested_parens() {
+    let nested = |depth| format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+    assert_eq!(kind_on_a_default_thread(nested(5000)), ErrorKind::CallDepth);
+    assert_eq!(Engine::new().eval(&nested(10)).unwrap(), Value::Integer(1));
+}
+
+#[test]
+fn deeply_nested_blocks() {
+    let nested = |depth| {
+        format!(
+            "{}let x = 1\n{}",
+            "if true then\n".repeat(depth),
+            "end\n".repeat(depth)
+        )
+    };
+    assert_eq!(kind_on_a_default_thread(nested(5000)), ErrorKind::CallDepth);
+    assert!(Engine::new().eval(&nested(5)).is_ok());
+}
+
+#[test]
+fn deep_expressions_in_recursion() {
+    // each call is shallow enough to parse, but running them nested isn't
+    let code = format!(
+        "proc f(n) then return {}f(n + 1){} end f(0)",
+        "(1 + ".repeat(5),
+        ")".repeat(5)
+    );
+    assert_eq!(kind_on_a_default_thread(code), ErrorKind::CallDepth);
+}
+
+#[test]
+fn interrupt_from_another_thread() {
+    let mut engine = Engine::new();
+    let interrupt = engine.interrupt_handle();
+    let interrupter = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        interrupt.interrupt();
+    });
+    assert_eq!(
+        kind(&mut engine, "while true then end"),
+        ErrorKind::Interrupted
+    );
+    interrupter.join().unwrap();
+    assert!(engine.eval("1 + 1").is_ok());
+}
+
+#[test]
+fn interrupts_between_runs_are_forgotten() {
+    let mut engine = Engine::new().with_limits(Limits::new().with_max_steps(100_000));
+    engine.interrupt_handle().interrupt();
+    let n = engine.eval("let n = 0\nwhile n < 10000 then n = n + 1 end\nn");
+    assert_eq!(n.unwrap(), Value::Integer(10000));
+}
@@ -0,0 +1,93 @@
+use bull_script::{bull_fn, bull_module, BufferIo, Engine, Value};
+
+struct Text;
+
+#[bull_module(name = "text")]
+impl Text {
+    /// Greets someone by name
+    ///
+    /// Everyone is greeted the same way
+    #[bull_fn]
+    fn greet(name: String) -> String {
+        format!("Hello, {}!", name)
+    }
+
+    #[bull_fn(name = "length")]
+    fn len(s: String) -> usize {
+        s.chars().count()
+    }
+
+    /// Not a proc, as it isn't marked with `#[bull_fn]`
+    #[allow(dead_code)]
+    fn hidden() {}
+}
+
+struct Shout;
+
+#[bull_module]
+impl Shout {
+    #[bull_fn]
+    fn loud(s: String) -> String {
+        s.to_uppercase()
+    }
+}
+
+/// Adds two ints
+#[bull_fn(name = "host.add")]
+fn add(a: i64, b: i64) -> i64 {
+    a + b
+}
+
+#[bull_fn]
+fn parse(s: String) -> Result<i64, std::num::ParseIntError> {
+    s.parse()
+}
+
+fn engine(io: &BufferIo) -> Engine {
+    let mut engine = Engine::new().with_io(io.clone());
+    engine.register_module(Text::bull_module());
+    engine.register_module(Shout::bull_module());
+    engine.register_native(add_native());
+    engine.register_native(parse_native());
+    engine
+}
+
+#[test]
+fn modules_and_functions_can_be_called() {
+    let mut engine = engine(&BufferIo::new());
+    assert_eq!(
+        engine.eval("text.greet(\"bull\")").unwrap(),
+        Value::String("Hello, bull!".to_string())
+    );
+    assert_eq!(
+        engine.eval("text.length(\"ünï\")").unwrap(),
+        Value::Integer(3)
+    );
+    assert_eq!(
+        engine.eval("shout.loud(\"moo\")").unwrap(),
+        Value::String("MOO".to_string())
+    );
+    assert_eq!(engine.eval("host.add(2, 3)").unwrap(), Value::Integer(5));
+    assert_eq!(engine.eval("parse(\"42\")").unwrap(), Value::Integer(42));
+    assert!(engine.eval("text.hidden()").is_err());
+    assert!(engine.eval("parse(\"bull\")").is_err());
+}
+
+#[test]
+fn help_shows_doc_comments() {
+    let io = BufferIo::new();
+    let mut engine = engine(&io);
+    engine
+        .eval("help(text.greet)\nhelp(host.add)\nhelp(text.length)")
+        .unwrap();
+    assert_eq!(
+        io.out(),
+        "proc text.greet(str)\n\
+         Greets someone by name\n\
+         \n\
+         Everyone is greeted the same way\n\
+         proc host.add(int, int)\n\
+         Adds two ints\n\
+         proc text.length(str)\n"
+    );
+}
@@ -0,0 +1,105 @@
+use bull_script::{Engine, ErrorKind, NativeModule, Value};
+
+fn engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.register_module(
+        NativeModule::new("host")
+            .with_fn("small", |n: u8| n as i64 * 2)
+            .with_fn("huge", |n: u64| n)
+            .with_fn("size", |n: usize| n)
+            .with_fn("greet", |name: String| format!("Hello, {}!", name))
+            .with_fn("parse", |s: String| s.parse::<i64>())
+            .with_fn("or_zero", |n: Option<i64>| n.unwrap_or(0))
+            .with_fn("first", |items: Vec<i64>| items.first().copied()),
+    );
+    engine
+}
+
+#[test]
+fn integers_out_of_range_are_rejected() {
+    let mut engine = engine();
+    assert_eq!(engine.eval("host.small(100)").unwrap(), Value::Integer(200));
+    for n in ["256", "-1", "100000000000000000000"] {
+        let error = engine.eval(&format!("host.small({})", n)).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Script);
+        assert!(error
+            .reason
+            .contains("Argument 1 of `host.small` expects int"));
+    }
+}
+
+#[test]
+fn integers_too_big_for_an_int_become_bigints() {
+    let mut engine = engine();
+    engine.set_global("max", Value::Integer(i64::MAX));
+    assert_eq!(
+        engine.eval("host.huge(max)").unwrap(),
+        Value::Integer(i64::MAX)
+    );
+    assert_eq!(engine.eval("host.size(7)").unwrap(), Value::Integer(7));
+
+    engine.register_fn("host.max_u64", || u64::MAX);
+    engine.register_fn("host.max_usize", || usize::MAX);
+    let expected = u64::MAX.to_string();
+    for name in ["host.max_u64", "host.max_usize"] {
+        let value = engine.call(name, vec![]).unwrap();
+        assert!(matches!(value, Value::BigInt(_)), "{:?}", value);
+        assert_eq!(value.to_string(), expected);
+        // and they can be passed back
+        engine.set_global("big", value);
+        assert_eq!(engine.eval("host.huge(big)").unwrap().to_string(), expected);
+    }
+}
+
+#[test]
+fn arguments_of_the_wrong_type() {
+    let mut engine = engine();
+    let error = engine.eval("host.greet(1)").unwrap_err();
+    assert_eq!(error.kind, ErrorKind::Script);
+    assert_eq!(
+        error.reason,
+        "Argument 1 of `host.greet` expects str, got int"
+    );
+    let error = engine.eval("host.first(\"not a list\")").unwrap_err();
+    assert_eq!(
+        error.reason,
+        "Argument 1 of `host.first` expects list[int], got str"
+    );
+}
+
+#[test]
+fn errors_are_raised_in_the_script() {
+    let mut engine = engine();
+    assert_eq!(
+        engine.eval("host.parse(\"42\")").unwrap(),
+        Value::Integer(42)
+    );
+    let error = engine.eval("let n = 1\nhost.parse(\"bull\")").unwrap_err();
+    assert_eq!(error.kind, ErrorKind::Script);
+    assert_eq!(error.reason, "invalid digit found in string");
+    assert_eq!(error.span.line_col().map(|(line, _)| line), Some(2));
+    // scripts can catch them like their own errors
+    engine
+        .eval("import std\nproc bad() then return host.parse(\"bull\") end\nstd.assert_throws(bad, \"invalid digit\")")
+        .unwrap();
+}
+
+#[test]
+fn null_is_none() {
+    let mut engine = engine();
+    assert_eq!(
+        engine.eval("host.or_zero(null)").unwrap(),
+        Value::Integer(0)
+    );
+    assert_eq!(engine.eval("host.or_zero(5)").unwrap(), Value::Integer(5));
+    assert_eq!(
+        engine
+            .call("host.first", vec![Value::List(vec![])])
+            .unwrap(),
+        Value::Null
+    );
+    assert_eq!(
+        engine.eval("let none = null\nhost.or_zero(none)").unwrap(),
+        Value::Integer(0)
+    );
+}